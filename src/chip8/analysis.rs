@@ -0,0 +1,327 @@
+use super::platform::Platform;
+
+// Static analysis of a loaded rom, used to work out things like which keys a game reads
+// without having to run it
+pub struct KeyUsage {
+    pub keys: [bool; 16], // Keys tested by EX9E/EXA1 with a value we could trace
+    pub unresolved_checks: bool, // Some EX9E/EXA1 used a register we couldn't trace
+    pub waits_for_key: bool, // FX0A is used, which accepts any key
+}
+
+impl KeyUsage {
+    pub fn any(&self) -> bool {
+        self.keys.contains(&true) || self.unresolved_checks || self.waits_for_key
+    }
+}
+
+// Which CHIP-8 keys a game seems to use for movement and firing
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Directions {
+    pub up: Option<usize>,
+    pub down: Option<usize>,
+    pub left: Option<usize>,
+    pub right: Option<usize>,
+    pub fire: Option<usize>,
+}
+
+impl Directions {
+    pub fn is_empty(&self) -> bool {
+        *self == Directions::default()
+    }
+
+    pub fn keys(&self) -> Vec<usize> {
+        [self.up, self.down, self.left, self.right, self.fire]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+// Keys commonly used as a fire button, in order of preference
+const FIRE_KEYS: [usize; 5] = [0x5, 0xF, 0xE, 0xA, 0x0];
+
+pub struct Analyzer {}
+
+impl Analyzer {
+    // Walks every instruction reachable from the start address, following jumps, calls and
+    // skips, while tracking registers loaded with constants (6XNN, 7XNN, 8XY0) so that the
+    // key value used by each EX9E/EXA1 can be resolved.
+    // Every address is only visited once, with the register values from the first path found.
+    pub fn key_usage(memory: &[u8], start: usize) -> KeyUsage {
+        let mut usage = KeyUsage {
+            keys: [false; 16],
+            unresolved_checks: false,
+            waits_for_key: false,
+        };
+        let mut visited = vec![false; memory.len()];
+        let mut pending: Vec<(usize, [Option<u8>; 16])> = vec![(start, [None; 16])];
+
+        while let Some((address, mut registers)) = pending.pop() {
+            if address + 1 >= memory.len() || visited[address] {
+                continue;
+            }
+            visited[address] = true;
+
+            let byte1 = memory[address];
+            let byte2 = memory[address + 1];
+            let nibbles = [byte1 >> 4, byte1 & 0xF, byte2 >> 4, byte2 & 0xF];
+            let x = nibbles[1] as usize;
+            let y = nibbles[2] as usize;
+            let nnn = ((byte1 as usize) << 8 | byte2 as usize) & 0xFFF;
+            let next = address + 2;
+
+            match nibbles {
+                [0x0, 0x0, 0xE, 0xE] => continue,
+                [0x1, ..] => {
+                    pending.push((nnn, registers));
+                    continue;
+                }
+                [0x2, ..] => {
+                    pending.push((nnn, registers));
+                    // The subroutine could have changed anything by the time it returns
+                    pending.push((next, [None; 16]));
+                    continue;
+                }
+                // Jump target depends on V0 at runtime
+                [0xB, ..] => continue,
+                [0x3 | 0x4 | 0x5 | 0x9, ..] => {
+                    pending.push((next + 2, registers));
+                }
+                [0xE, _, 0x9, 0xE] | [0xE, _, 0xA, 0x1] => {
+                    match registers[x] {
+                        Some(key) => usage.keys[(key & 0xF) as usize] = true,
+                        None => usage.unresolved_checks = true,
+                    }
+                    pending.push((next + 2, registers));
+                }
+                [0x6, ..] => registers[x] = Some(byte2),
+                [0x7, ..] => registers[x] = registers[x].map(|value| value.wrapping_add(byte2)),
+                [0x8, _, _, 0x0] => registers[x] = registers[y],
+                [0x8, ..] => {
+                    registers[x] = None;
+                    registers[0xF] = None;
+                }
+                [0xC, ..] => registers[x] = None,
+                [0xD, ..] => registers[0xF] = None,
+                [0xF, _, 0x0, 0xA] => {
+                    usage.waits_for_key = true;
+                    registers[x] = None;
+                }
+                [0xF, _, 0x0, 0x7] => registers[x] = None,
+                [0xF, _, 0x1, 0xE] => registers[0xF] = None,
+                [0xF, _, 0x6, 0x5] => {
+                    for register in registers.iter_mut().take(x + 1) {
+                        *register = None;
+                    }
+                }
+                _ => {}
+            }
+
+            pending.push((next, registers));
+        }
+
+        usage
+    }
+
+    // Guesses directions from the keys a rom tests, based on the layouts games commonly use:
+    // the 2/4/6/8 cross around 5, or vertical pairs like 1/4 and C/D in Pong style games
+    pub fn directions(usage: &KeyUsage) -> Directions {
+        let used = |key: usize| usage.keys[key];
+        let mut directions = Directions::default();
+
+        let vertical_cross = used(0x2) && used(0x8);
+        let horizontal_cross = used(0x4) && used(0x6);
+        if vertical_cross || horizontal_cross {
+            directions.up = Some(0x2).filter(|key| used(*key));
+            directions.down = Some(0x8).filter(|key| used(*key));
+            directions.left = Some(0x4).filter(|key| used(*key));
+            directions.right = Some(0x6).filter(|key| used(*key));
+        } else if used(0x1) && used(0x4) {
+            directions.up = Some(0x1);
+            directions.down = Some(0x4);
+        } else if used(0xC) && used(0xD) {
+            directions.up = Some(0xC);
+            directions.down = Some(0xD);
+        } else {
+            return directions;
+        }
+
+        let taken = [
+            directions.up,
+            directions.down,
+            directions.left,
+            directions.right,
+        ];
+        directions.fire = FIRE_KEYS
+            .into_iter()
+            .find(|key| used(*key) && !taken.contains(&Some(*key)));
+
+        directions
+    }
+
+    // Marks the address of every instruction reachable from the start address, following
+    // jumps, calls and both sides of every skip. Anything left unmarked is probably data.
+    pub fn reachable(memory: &[u8], start: usize) -> Vec<bool> {
+        let mut reachable = vec![false; memory.len()];
+        let mut pending = vec![start];
+
+        while let Some(address) = pending.pop() {
+            if address + 1 >= memory.len() || reachable[address] {
+                continue;
+            }
+            reachable[address] = true;
+
+            let byte1 = memory[address];
+            let byte2 = memory[address + 1];
+            let nnn = ((byte1 as usize) << 8 | byte2 as usize) & 0xFFF;
+            let next = address + 2;
+
+            match [byte1 >> 4, byte1 & 0xF, byte2 >> 4, byte2 & 0xF] {
+                // Return, and the SCHIP exit instruction
+                [0x0, 0x0, 0xE, 0xE] | [0x0, 0x0, 0xF, 0xD] => {}
+                [0x1, ..] => pending.push(nnn),
+                [0x2, ..] => pending.extend([nnn, next]),
+                [0xB, ..] => {}
+                [0x3 | 0x4 | 0x5 | 0x9, ..] | [0xE, _, 0x9, 0xE] | [0xE, _, 0xA, 0x1] => {
+                    pending.extend([next, next + 2])
+                }
+                // XO-CHIP's F000 NNNN is four bytes long
+                [0xF, 0x0, 0x0, 0x0] => pending.push(next + 2),
+                _ => pending.push(next),
+            }
+        }
+
+        reachable
+    }
+
+    // Guesses which platform a rom was written for from the instructions it uses, since
+    // SCHIP and XO-CHIP only added instructions on top of the original ones
+    pub fn detect_platform(memory: &[u8], start: usize) -> Platform {
+        let mut platform = Platform::Chip8;
+
+        for (address, _) in Analyzer::reachable(memory, start)
+            .iter()
+            .enumerate()
+            .filter(|(_, reachable)| **reachable)
+        {
+            let byte1 = memory[address];
+            let byte2 = memory[address + 1];
+            match [byte1 >> 4, byte1 & 0xF, byte2 >> 4, byte2 & 0xF] {
+                // Scroll up, bitplanes, long I, audio pattern, pitch and register ranges
+                [0x0, 0x0, 0xD, _]
+                | [0xF, _, 0x0, 0x1]
+                | [0xF, 0x0, 0x0, 0x0]
+                | [0xF, 0x0, 0x0, 0x2]
+                | [0xF, _, 0x3, 0xA]
+                | [0x5, _, _, 0x2]
+                | [0x5, _, _, 0x3] => return Platform::XoChip,
+                // Scrolling, hires, exit, big font and flag registers. DXY0 isn't counted on
+                // its own, CHIP-8 roms use it by mistake as it just draws nothing on the VIP.
+                [0x0, 0x0, 0xC, _]
+                | [0x0, 0x0, 0xF, 0xB..=0xF]
+                | [0xF, _, 0x3, 0x0]
+                | [0xF, _, 0x7, 0x5]
+                | [0xF, _, 0x8, 0x5] => platform = Platform::SuperChip,
+                _ => {}
+            }
+        }
+
+        platform
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Places a program at 0x200 in an otherwise empty memory
+    fn memory_with(program: &[u16]) -> Vec<u8> {
+        let mut memory = vec![0; 4096];
+        for (i, instruction) in program.iter().enumerate() {
+            memory[0x200 + i * 2..0x202 + i * 2].copy_from_slice(&instruction.to_be_bytes());
+        }
+        memory
+    }
+
+    fn usage_of(keys: &[usize]) -> KeyUsage {
+        let mut usage = KeyUsage {
+            keys: [false; 16],
+            unresolved_checks: false,
+            waits_for_key: false,
+        };
+        for key in keys {
+            usage.keys[*key] = true;
+        }
+        usage
+    }
+
+    #[test]
+    fn constants_reach_key_checks() {
+        // V1 = 5, V2 = 2 + 4, test both, then loop
+        let memory = memory_with(&[0x6105, 0x6202, 0x7204, 0xE19E, 0xE2A1, 0x1200]);
+        let usage = Analyzer::key_usage(&memory, 0x200);
+
+        assert_eq!(usage.keys, usage_of(&[0x5, 0x6]).keys);
+        assert!(!usage.unresolved_checks);
+        assert!(!usage.waits_for_key);
+    }
+
+    #[test]
+    fn unknown_registers_are_unresolved() {
+        // V1 is random, and V2's constant is lost across the call
+        let memory = memory_with(&[0xC1FF, 0xE19E, 0x6203, 0x220A, 0xE2A1, 0x00EE]);
+        let usage = Analyzer::key_usage(&memory, 0x200);
+
+        assert!(!usage.keys.contains(&true));
+        assert!(usage.unresolved_checks);
+    }
+
+    #[test]
+    fn cross_around_five() {
+        let directions = Analyzer::directions(&usage_of(&[0x2, 0x4, 0x5, 0x6, 0x8]));
+        assert_eq!(
+            directions,
+            Directions {
+                up: Some(0x2),
+                down: Some(0x8),
+                left: Some(0x4),
+                right: Some(0x6),
+                fire: Some(0x5),
+            }
+        );
+    }
+
+    #[test]
+    fn pong_style_pairs() {
+        let directions = Analyzer::directions(&usage_of(&[0x1, 0x4, 0xC, 0xD]));
+        assert_eq!(
+            directions,
+            Directions {
+                up: Some(0x1),
+                down: Some(0x4),
+                ..Default::default()
+            }
+        );
+
+        let directions = Analyzer::directions(&usage_of(&[0xC, 0xD, 0xF]));
+        assert_eq!((directions.up, directions.down), (Some(0xC), Some(0xD)));
+        assert_eq!(directions.fire, Some(0xF));
+
+        assert!(Analyzer::directions(&usage_of(&[0x1, 0x5])).is_empty());
+    }
+
+    #[test]
+    fn platform_from_instructions() {
+        let chip8 = memory_with(&[0xA300, 0xD120, 0x1200]);
+        assert_eq!(Analyzer::detect_platform(&chip8, 0x200), Platform::Chip8);
+
+        let schip = memory_with(&[0x00FF, 0xD120, 0x1202]);
+        assert_eq!(
+            Analyzer::detect_platform(&schip, 0x200),
+            Platform::SuperChip
+        );
+
+        let xo_chip = memory_with(&[0x00FF, 0xF002, 0x1202]);
+        assert_eq!(Analyzer::detect_platform(&xo_chip, 0x200), Platform::XoChip);
+    }
+}
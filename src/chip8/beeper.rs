@@ -0,0 +1,56 @@
+use super::square::SquareWave;
+use rodio::{OutputStreamHandle, Sink, Source};
+use std::time::Duration;
+
+pub const DEFAULT_VOLUME: f32 = 0.20;
+pub const DEFAULT_BEEP_FREQUENCY: f32 = 240.0;
+const SAMPLE_RATE: u32 = 44100;
+
+// The tone played while the sound timer is above zero.
+pub struct Beeper {
+    sink: Sink,
+    frequency: f32,
+}
+
+impl Beeper {
+    pub fn new(stream_handle: &OutputStreamHandle) -> Self {
+        let mut beeper = Self {
+            sink: Sink::try_new(stream_handle).unwrap(),
+            frequency: 0.0,
+        };
+        beeper.set_frequency(DEFAULT_BEEP_FREQUENCY);
+        beeper.set_volume(DEFAULT_VOLUME);
+        beeper
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        if frequency == self.frequency {
+            return;
+        }
+        self.frequency = frequency;
+
+        // Replacing the wave also pauses the sink, which play() undoes when it's needed
+        self.sink.clear();
+        let wave = SquareWave::new(frequency, SAMPLE_RATE);
+        let source = wave
+            .take_duration(Duration::from_secs_f32(0.25))
+            .repeat_infinite();
+        self.sink.append(source);
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume.clamp(0.0, 1.0));
+    }
+
+    pub fn play(&self) {
+        self.sink.play();
+    }
+
+    pub fn pause(&self) {
+        self.sink.pause();
+    }
+}
@@ -0,0 +1,120 @@
+use super::database::RomProfile;
+use super::octo::Octo;
+use serde_json::Value;
+
+// Octo shares programs as "cartridges", GIFs with a label drawn in the high bits of the pixels
+// and data hidden in the low two. The data is JSON holding the program's Octo source and the
+// options it was saved with.
+pub struct Cartridge {}
+
+impl Cartridge {
+    pub fn is_cartridge(data: &[u8]) -> bool {
+        data.starts_with(b"GIF87a") || data.starts_with(b"GIF89a")
+    }
+
+    // Assembles the program, and turns its options into a profile like the rom database's
+    pub fn load(data: &[u8]) -> Result<(Vec<u8>, RomProfile), String> {
+        let json: Value = serde_json::from_str(&Cartridge::payload(data)?)
+            .map_err(|e| format!("the cartridge's data is corrupt, {}", e))?;
+        let fields = json.as_object().ok_or("the cartridge's data is corrupt")?;
+        let program = fields
+            .get("program")
+            .and_then(Value::as_str)
+            .ok_or("the cartridge has no program")?;
+
+        let rom =
+            Octo::assemble(program).map_err(|e| format!("the program doesn't assemble, {}", e))?;
+        let profile = fields
+            .get("options")
+            .and_then(Value::as_object)
+            .map(RomProfile::from_octo_options)
+            .unwrap_or_default();
+        Ok((rom, profile))
+    }
+
+    // Four pixels make a byte, top bits first, across all the frames. The first four bytes are
+    // the length of the JSON after them.
+    fn payload(data: &[u8]) -> Result<String, String> {
+        let pixels = Cartridge::pixels(data).map_err(|e| format!("the GIF is corrupt, {}", e))?;
+        let bytes: Vec<u8> = pixels
+            .chunks_exact(4)
+            .map(|chunk| chunk.iter().fold(0, |byte, pixel| byte << 2 | pixel & 0b11))
+            .collect();
+
+        let length = bytes
+            .get(..4)
+            .map(|length| u32::from_be_bytes(length.try_into().unwrap()) as usize)
+            .ok_or("the image has no cartridge data")?;
+        let json = bytes
+            .get(4..4 + length)
+            .ok_or("the image isn't an Octo cartridge")?;
+        String::from_utf8(json.to_vec())
+            .map_err(|_| "the image isn't an Octo cartridge".to_string())
+    }
+
+    // The palette indices of every frame, one after another
+    fn pixels(data: &[u8]) -> Result<Vec<u8>, gif::DecodingError> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(data)?;
+
+        let mut pixels = Vec::new();
+        while let Some(frame) = decoder.read_next_frame()? {
+            pixels.extend_from_slice(&frame.buffer);
+        }
+        Ok(pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Hides the JSON in the low bits of a GIF the way Octo does, under a label in the high
+    // bits, split across two frames
+    fn cartridge(json: &str) -> Vec<u8> {
+        let mut bytes = (json.len() as u32).to_be_bytes().to_vec();
+        bytes.extend_from_slice(json.as_bytes());
+        let mut pixels: Vec<u8> = bytes
+            .iter()
+            .flat_map(|byte| [6, 4, 2, 0].map(|shift| byte >> shift & 0b11 | 0b100))
+            .collect();
+        pixels.resize(2 * 32 * 32, 0);
+
+        let mut data = Vec::new();
+        let palette = [0; 3 * 8];
+        let mut encoder = gif::Encoder::new(&mut data, 32, 32, &palette).unwrap();
+        for half in pixels.chunks(32 * 32) {
+            let frame = gif::Frame::from_indexed_pixels(32, 32, half.to_vec(), None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        drop(encoder);
+        data
+    }
+
+    #[test]
+    fn program_and_options() {
+        let data = cartridge(
+            r##"{"program": ": main\n  clear\n  loop again",
+                 "options": {"tickrate": "20", "shiftQuirks": true,
+                             "backgroundColor": "#996600", "fillColor": "#FFCC00"}}"##,
+        );
+        assert!(Cartridge::is_cartridge(&data));
+
+        let (rom, profile) = Cartridge::load(&data).unwrap();
+        assert_eq!(rom, [0x00, 0xE0, 0x12, 0x02]);
+        assert_eq!(profile.speed, Some(20));
+        assert!(profile.quirks.unwrap().shift_vx);
+        assert_eq!(profile.palette.unwrap().on, [0xFF, 0xCC, 0x00]);
+    }
+
+    #[test]
+    fn not_a_cartridge() {
+        let data = cartridge(r#"{"title": "no program"}"#);
+        assert_eq!(
+            Cartridge::load(&data).unwrap_err(),
+            "the cartridge has no program"
+        );
+        assert!(Cartridge::load(&data[..40]).is_err());
+    }
+}
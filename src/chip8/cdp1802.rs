@@ -0,0 +1,358 @@
+// The RCA CDP1802, the COSMAC VIP's processor, which the CHIP-8 interpreter itself ran on and
+// which 0NNN hands control to. Every instruction of the 1802 is supported. The 1804's extra
+// instructions (the 68 prefix) are not.
+
+// What the processor is wired to. Addresses are the full 16 bits, for the bus to decode.
+pub trait Bus {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    fn output(&mut self, port: u8, value: u8); // OUT 1-7
+    fn input(&mut self, port: u8) -> u8; // INP 1-7
+    fn flag(&self, flag: u8) -> bool; // EF1-EF4
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Cdp1802 {
+    pub r: [u16; 16], // Scratchpad registers, any of which can be the program counter
+    pub p: u8,        // Which register is the program counter
+    pub x: u8,        // Which register addresses memory for ALU and stack instructions
+    pub d: u8,        // Accumulator
+    pub df: bool,     // Carry, or no borrow after a subtraction
+    pub t: u8,        // X and P saved by an interrupt or MARK
+    pub q: bool,      // Output flip-flop, the VIP's tone
+    pub ie: bool,     // Interrupts enabled
+    pub idle: bool,   // Stopped by IDL until the next interrupt or DMA
+}
+
+impl Cdp1802 {
+    // Runs one instruction and returns how many machine cycles it took
+    pub fn step(&mut self, bus: &mut impl Bus) -> u32 {
+        if self.idle {
+            return 1;
+        }
+
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0xF) as usize;
+        let x = self.x as usize;
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let condition = self.condition(n & 7, bus) != (n & 8 != 0);
+                let p = self.p as usize;
+                if condition {
+                    let target = bus.read(self.r[p]);
+                    self.r[p] = (self.r[p] & 0xFF00) | target as u16;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(1);
+                }
+            }
+            0x4 => {
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n], self.d),
+            0x6 => match n {
+                0 => self.r[x] = self.r[x].wrapping_add(1),
+                1..=7 => {
+                    bus.output(n as u8, bus.read(self.r[x]));
+                    self.r[x] = self.r[x].wrapping_add(1);
+                }
+                8 => {}
+                _ => {
+                    self.d = bus.input(n as u8 - 8);
+                    bus.write(self.r[x], self.d);
+                }
+            },
+            0x7 => self.execute_7(opcode, bus),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = (self.r[n] & 0xFF00) | self.d as u16,
+            0xB => self.r[n] = (self.r[n] & 0x00FF) | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch(n, bus);
+                return 3;
+            }
+            0xD => self.p = n as u8,
+            0xE => self.x = n as u8,
+            _ => self.execute_f(opcode, bus),
+        }
+        2
+    }
+
+    // Starts the interrupt routine in R1, with X set to the stack in R2, if interrupts are
+    // enabled. Wakes the processor from IDL either way.
+    pub fn interrupt(&mut self) {
+        self.idle = false;
+        if self.ie {
+            self.t = self.x << 4 | self.p;
+            self.p = 1;
+            self.x = 2;
+            self.ie = false;
+        }
+    }
+
+    fn fetch(&mut self, bus: &impl Bus) -> u8 {
+        let p = self.p as usize;
+        let byte = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        byte
+    }
+
+    // The conditions of the short branches, in the order of their low 3 bits
+    fn condition(&self, number: usize, bus: &impl Bus) -> bool {
+        match number {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag as u8 - 3),
+        }
+    }
+
+    // C0-CF: long branches take the next two bytes as the address, long skips jump over them
+    fn long_branch(&mut self, n: usize, bus: &impl Bus) {
+        let p = self.p as usize;
+        let (skip, condition) = match n {
+            0x4 => (true, false), // NOP
+            0xC => (true, self.ie),
+            0x5 => (true, !self.q),
+            0x6 => (true, self.d != 0),
+            0x7 => (true, !self.df),
+            0x8 => (true, true),
+            0xD => (true, self.q),
+            0xE => (true, self.d == 0),
+            0xF => (true, self.df),
+            _ => (false, self.condition(n & 3, bus) != (n & 8 != 0)),
+        };
+
+        if skip {
+            if condition {
+                self.r[p] = self.r[p].wrapping_add(2);
+            }
+        } else if condition {
+            let high = bus.read(self.r[p]) as u16;
+            let low = bus.read(self.r[p].wrapping_add(1)) as u16;
+            self.r[p] = high << 8 | low;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(2);
+        }
+    }
+
+    fn execute_7(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let x = self.x as usize;
+        match opcode {
+            // RET and DIS
+            0x70 | 0x71 => {
+                let value = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0xF;
+                self.ie = opcode == 0x70;
+            }
+            0x72 => {
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x73 => {
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x74 => self.add(bus.read(self.r[x]), self.df),
+            0x75 => self.subtract(bus.read(self.r[x]), self.d, self.df),
+            0x76 => {
+                let carry = self.d & 1 != 0;
+                self.d = self.d >> 1 | (self.df as u8) << 7;
+                self.df = carry;
+            }
+            0x77 => self.subtract(self.d, bus.read(self.r[x]), self.df),
+            0x78 => bus.write(self.r[x], self.t),
+            // MARK, which saves X and P on the stack for a subroutine call
+            0x79 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0x7A => self.q = false,
+            0x7B => self.q = true,
+            0x7C => {
+                let value = self.fetch(bus);
+                self.add(value, self.df);
+            }
+            0x7D => {
+                let value = self.fetch(bus);
+                self.subtract(value, self.d, self.df);
+            }
+            0x7E => {
+                let carry = self.d & 0x80 != 0;
+                self.d = self.d << 1 | self.df as u8;
+                self.df = carry;
+            }
+            _ => {
+                let value = self.fetch(bus);
+                self.subtract(self.d, value, self.df);
+            }
+        }
+    }
+
+    fn execute_f(&mut self, opcode: u8, bus: &mut impl Bus) {
+        let n = opcode & 0xF;
+        if n == 0x6 {
+            self.df = self.d & 1 != 0;
+            self.d >>= 1;
+            return;
+        }
+        if n == 0xE {
+            self.df = self.d & 0x80 != 0;
+            self.d <<= 1;
+            return;
+        }
+
+        // F0-F7 take their operand from M(R(X)), F8-FF from the byte after the instruction
+        let value = if n < 8 {
+            bus.read(self.r[self.x as usize])
+        } else {
+            self.fetch(bus)
+        };
+        match n & 7 {
+            0 => self.d = value,
+            1 => self.d |= value,
+            2 => self.d &= value,
+            3 => self.d ^= value,
+            4 => self.add(value, false),
+            5 => self.subtract(value, self.d, true),
+            _ => self.subtract(self.d, value, true),
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let sum = self.d as u16 + value as u16 + carry as u16;
+        self.d = sum as u8;
+        self.df = sum > 0xFF;
+    }
+
+    // DF is set when there was no borrow, and a clear DF borrows one more
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, no_borrow: bool) {
+        let difference = minuend as i16 - subtrahend as i16 - !no_borrow as i16;
+        self.d = difference as u8;
+        self.df = difference >= 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct TestBus {
+        memory: Vec<u8>,
+        outputs: Vec<(u8, u8)>,
+        flags: [bool; 4],
+    }
+
+    impl Bus for TestBus {
+        fn read(&self, address: u16) -> u8 {
+            self.memory[address as usize]
+        }
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory[address as usize] = value;
+        }
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs.push((port, value));
+        }
+        fn input(&mut self, port: u8) -> u8 {
+            port * 0x10
+        }
+        fn flag(&self, flag: u8) -> bool {
+            self.flags[flag as usize - 1]
+        }
+    }
+
+    // Runs the program from 0x000 with R0 as the program counter
+    fn run(program: &[u8], steps: usize) -> (Cdp1802, TestBus, u32) {
+        let mut bus = TestBus {
+            memory: vec![0; 0x100],
+            outputs: Vec::new(),
+            flags: [false, false, true, false],
+        };
+        bus.memory[..program.len()].copy_from_slice(program);
+        let mut cpu = Cdp1802::default();
+        let cycles = (0..steps).map(|_| cpu.step(&mut bus)).sum();
+        (cpu, bus, cycles)
+    }
+
+    #[test]
+    fn arithmetic_and_carry() {
+        // LDI 05, ADI FF, ADCI 00
+        let (cpu, _, cycles) = run(&[0xF8, 0x05, 0xFC, 0xFF, 0x7C, 0x00], 2);
+        assert_eq!((cpu.d, cpu.df, cycles), (0x04, true, 4));
+        let (cpu, _, _) = run(&[0xF8, 0x05, 0xFC, 0xFF, 0x7C, 0x00], 3);
+        assert_eq!((cpu.d, cpu.df), (0x05, false));
+
+        // LDI 01, SMI 02 borrows, leaving DF clear
+        let (cpu, _, _) = run(&[0xF8, 0x01, 0xFF, 0x02], 2);
+        assert_eq!((cpu.d, cpu.df), (0xFF, false));
+    }
+
+    #[test]
+    fn shifts() {
+        // LDI 81, SHR, then SHL
+        let (cpu, _, _) = run(&[0xF8, 0x81, 0xF6], 2);
+        assert_eq!((cpu.d, cpu.df), (0x40, true));
+        let (cpu, _, _) = run(&[0xF8, 0x81, 0xFE], 2);
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+    }
+
+    #[test]
+    fn register_transfers() {
+        // LDI 12, PHI 5, LDI 34, PLO 5, INC 5, GLO 5
+        let (cpu, _, _) = run(&[0xF8, 0x12, 0xB5, 0xF8, 0x34, 0xA5, 0x15, 0x85], 6);
+        assert_eq!((cpu.r[5], cpu.d), (0x1235, 0x35));
+    }
+
+    #[test]
+    fn branches() {
+        // BZ 10 isn't taken with D set, B3 20 is taken as EF3 is high
+        let (cpu, _, _) = run(&[0xF8, 0x01, 0x32, 0x10, 0x36, 0x20], 3);
+        assert_eq!(cpu.r[0], 0x20);
+
+        // LBR 0034 takes three cycles
+        let (cpu, _, cycles) = run(&[0xC0, 0x00, 0x34], 1);
+        assert_eq!((cpu.r[0], cycles), (0x34, 3));
+    }
+
+    #[test]
+    fn input_and_output() {
+        // SEX 1 with R1 at 0, then OUT 2 sends the byte at M(R1), INP 3 stores into it
+        let (cpu, bus, _) = run(&[0xE1, 0x62, 0x6B], 3);
+        assert_eq!(bus.outputs, [(2, 0xE1)]);
+        assert_eq!((cpu.d, cpu.r[1], bus.memory[1]), (0x30, 1, 0x30));
+    }
+
+    #[test]
+    fn interrupt_and_return() {
+        let mut bus = TestBus {
+            memory: vec![0; 0x100],
+            outputs: Vec::new(),
+            flags: [false; 4],
+        };
+        // The routine at 0x80 returns straight away with RET, which reads X and P from M(R2)
+        bus.memory[0x80] = 0x70;
+        let mut cpu = Cdp1802 {
+            p: 3,
+            x: 5,
+            ie: true,
+            ..Default::default()
+        };
+        cpu.r[1] = 0x80;
+        cpu.r[2] = 0x90;
+        cpu.interrupt();
+        assert_eq!((cpu.p, cpu.x, cpu.t, cpu.ie), (1, 2, 0x53, false));
+
+        bus.memory[0x90] = cpu.t;
+        cpu.step(&mut bus);
+        assert_eq!((cpu.p, cpu.x, cpu.ie, cpu.r[2]), (3, 5, true, 0x91));
+    }
+}
@@ -0,0 +1,117 @@
+use super::constants::{REAL_HEIGHT, REAL_WIDTH};
+use super::execution::PixelState;
+
+// The VP-590 colour board that CHIP-8X was written for. It doesn't change the 64x32 picture,
+// it colours it: pixels that are off show the background colour, and pixels that are on show
+// the foreground colour of their zone. Zones are 8 pixels wide and 1 tall, the finest that
+// BXYN can colour. BXY0 colours them in blocks of 8x4.
+pub const ZONE_WIDTH: usize = 8;
+pub const BLOCK_HEIGHT: usize = 4;
+const COLUMNS: usize = REAL_WIDTH / ZONE_WIDTH;
+
+// Foreground colours by their 3 bit number, which is red, blue and green from the lowest bit
+const FOREGROUNDS: [[u8; 3]; 8] = [
+    [0x00, 0x00, 0x00], // Black
+    [0xff, 0x00, 0x00], // Red
+    [0x00, 0x00, 0xff], // Blue
+    [0xff, 0x00, 0xff], // Violet
+    [0x00, 0xff, 0x00], // Green
+    [0xff, 0xff, 0x00], // Yellow
+    [0x00, 0xff, 0xff], // Aqua
+    [0xff, 0xff, 0xff], // White
+];
+// Background colours in the order 02A0 cycles through them
+const BACKGROUNDS: [[u8; 3]; 4] = [
+    [0x00, 0x00, 0x80], // Blue
+    [0x00, 0x00, 0x00], // Black
+    [0x00, 0x80, 0x00], // Green
+    [0x80, 0x00, 0x00], // Red
+];
+const DEFAULT_FOREGROUND: u8 = 1;
+
+#[derive(Clone, Debug)]
+pub struct ColorBoard {
+    background: usize,
+    zones: Vec<u8>, // COLUMNS zones a row, REAL_HEIGHT rows
+}
+
+impl Default for ColorBoard {
+    fn default() -> Self {
+        Self {
+            background: 0,
+            zones: vec![DEFAULT_FOREGROUND; COLUMNS * REAL_HEIGHT],
+        }
+    }
+}
+
+impl ColorBoard {
+    // 02A0
+    pub fn cycle_background(&mut self) {
+        self.background = (self.background + 1) % BACKGROUNDS.len();
+    }
+
+    // Colours the zones in the given columns and rows, leaving out any past the screen's edge
+    pub fn set_zones(&mut self, columns: (usize, usize), rows: (usize, usize), color: u8) {
+        let (left, width) = columns;
+        let (top, height) = rows;
+        for row in (top..top + height).filter(|row| *row < REAL_HEIGHT) {
+            for column in (left..left + width).filter(|column| *column < COLUMNS) {
+                self.zones[row * COLUMNS + column] = color & 0b111;
+            }
+        }
+    }
+
+    // The board only covers 32 rows, so any below them on a taller display share the last one
+    pub fn color(&self, index: usize, state: PixelState) -> [u8; 3] {
+        match state {
+            PixelState::Off => BACKGROUNDS[self.background],
+            PixelState::On => {
+                let (x, y) = (
+                    index % REAL_WIDTH,
+                    (index / REAL_WIDTH).min(REAL_HEIGHT - 1),
+                );
+                FOREGROUNDS[self.zones[y * COLUMNS + x / ZONE_WIDTH] as usize]
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn foreground(board: &ColorBoard, x: usize, y: usize) -> [u8; 3] {
+        board.color(y * REAL_WIDTH + x, PixelState::On)
+    }
+
+    #[test]
+    fn background_cycles_through_four_colours() {
+        let mut board = ColorBoard::default();
+        let mut seen = Vec::new();
+        for _ in 0..5 {
+            seen.push(board.color(0, PixelState::Off));
+            board.cycle_background();
+        }
+        assert_eq!(seen[..4], BACKGROUNDS);
+        assert_eq!(seen[4], BACKGROUNDS[0]);
+    }
+
+    #[test]
+    fn zones_are_clipped_to_the_board() {
+        let mut board = ColorBoard::default();
+        board.set_zones((6, 4), (30, 4), 0b1100);
+
+        assert_eq!(foreground(&board, 48, 30), FOREGROUNDS[4]);
+        assert_eq!(foreground(&board, 63, 31), FOREGROUNDS[4]);
+        assert_eq!(foreground(&board, 47, 31), FOREGROUNDS[1]);
+        assert_eq!(foreground(&board, 48, 29), FOREGROUNDS[1]);
+    }
+
+    #[test]
+    fn rows_past_the_board_share_its_last_row() {
+        let mut board = ColorBoard::default();
+        board.set_zones((0, 1), (31, 1), 7);
+        assert_eq!(foreground(&board, 0, 40), FOREGROUNDS[7]);
+        assert_eq!(foreground(&board, 8, 63), FOREGROUNDS[1]);
+    }
+}
@@ -0,0 +1,199 @@
+use super::screen::Palette;
+use super::toml::{Toml, TomlTable, TomlValue};
+use super::utils::Utils;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+
+// Settings that can be given in config.toml, both globally and per rom. Anything missing
+// falls back to the rom database and then to the built in defaults.
+#[derive(Clone, Debug, Default)]
+pub struct Settings {
+    pub speed: Option<u32>, // Instructions per frame
+    pub palette: Option<Palette>,
+    pub volume: Option<f32>,
+    pub beep_frequency: Option<f32>,
+    pub keymap: Option<TomlTable>, // Same format as keymap.toml
+    // Only used globally, as the window and launcher exist before any rom is picked
+    pub scale: Option<u32>,
+    pub rom_dir: Option<PathBuf>,
+}
+
+impl Settings {
+    pub fn from_table(table: &TomlTable, global: bool) -> Result<Self, String> {
+        let mut settings = Settings::default();
+
+        for (name, value) in table {
+            let invalid = || format!("invalid value for \"{}\"", name);
+            match name.as_str() {
+                "speed" => {
+                    settings.speed = Some(
+                        value
+                            .as_integer()
+                            .and_then(|speed| u32::try_from(speed).ok())
+                            .filter(|speed| *speed > 0)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "palette" => {
+                    let colors: Option<Vec<[u8; 3]>> = value.as_array().and_then(|colors| {
+                        colors
+                            .iter()
+                            .map(|color| color.as_str().and_then(Palette::parse_color))
+                            .collect()
+                    });
+                    match colors.as_deref() {
+                        Some([off, on]) => settings.palette = Some(Palette { off: *off, on: *on }),
+                        _ => {
+                            return Err(
+                                "palette must be two colors like [\"#000000\", \"#ffffff\"]"
+                                    .to_string(),
+                            )
+                        }
+                    }
+                }
+                "volume" => {
+                    settings.volume = Some(
+                        value
+                            .as_float()
+                            .filter(|volume| (0.0..=1.0).contains(volume))
+                            .ok_or("volume must be between 0.0 and 1.0")?
+                            as f32,
+                    )
+                }
+                "beep_frequency" => {
+                    settings.beep_frequency = Some(
+                        value
+                            .as_float()
+                            .filter(|frequency| *frequency > 0.0)
+                            .ok_or_else(invalid)? as f32,
+                    )
+                }
+                "keymap" => settings.keymap = Some(value.as_table().ok_or_else(invalid)?.clone()),
+                "scale" if global => {
+                    settings.scale = Some(
+                        value
+                            .as_integer()
+                            .and_then(|scale| u32::try_from(scale).ok())
+                            .filter(|scale| *scale > 0)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "rom_dir" if global => {
+                    settings.rom_dir = Some(PathBuf::from(value.as_str().ok_or_else(invalid)?))
+                }
+                // Handled by Config
+                "save_settings" | "rom" if global => {}
+                _ => return Err(format!("unknown setting \"{}\"", name)),
+            }
+        }
+
+        Ok(settings)
+    }
+}
+
+// The user's config.toml from the config directory.
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    pub settings: Settings,
+    pub roms: BTreeMap<String, Settings>, // Keyed by the SHA-1 of the rom
+    pub save_settings: bool,              // Write settings changed with hotkeys back to the file
+}
+
+impl Config {
+    // A config file that's missing or broken just means the defaults are used
+    pub fn load() -> Self {
+        let Some(path) = Config::path() else {
+            return Config::default();
+        };
+        let Ok(text) = fs::read_to_string(&path) else {
+            return Config::default();
+        };
+
+        match Toml::parse(&text)
+            .map_err(|e| e.to_string())
+            .and_then(|table| Config::from_table(&table))
+        {
+            Ok(config) => config,
+            Err(e) => {
+                println!("Ignoring config {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    // Looks like:
+    //
+    // speed = 15
+    // volume = 0.5
+    //
+    // [rom."f13766c14aeb02ad8d4d103cb5eadd282d20cddc"]
+    // palette = ["#1a1c2c", "#f4f4f4"]
+    pub fn from_table(table: &TomlTable) -> Result<Self, String> {
+        let mut config = Config {
+            settings: Settings::from_table(table, true)?,
+            ..Default::default()
+        };
+
+        if let Some(save_settings) = table.get("save_settings") {
+            config.save_settings = save_settings
+                .as_bool()
+                .ok_or("save_settings must be true or false")?;
+        }
+
+        if let Some(roms) = table.get("rom") {
+            for (sha1, rom) in roms.as_table().ok_or("rom must be a table")? {
+                let rom = rom
+                    .as_table()
+                    .ok_or(format!("rom.\"{}\" must be a table", sha1))?;
+                let settings = Settings::from_table(rom, false)
+                    .map_err(|e| format!("{} in rom.\"{}\"", e, sha1))?;
+                config.roms.insert(sha1.to_lowercase(), settings);
+            }
+        }
+
+        Ok(config)
+    }
+
+    pub fn path() -> Option<PathBuf> {
+        Utils::config_dir().map(|dir| dir.join("config.toml"))
+    }
+
+    pub fn rom(&self, sha1: &str) -> Settings {
+        self.roms.get(sha1).cloned().unwrap_or_default()
+    }
+
+    // Writes a setting changed at runtime back to the file, if the user asked for that.
+    // Passing a hash saves it in that rom's section instead of globally.
+    pub fn save(&self, sha1: Option<&str>, key: &str, value: TomlValue) {
+        if !self.save_settings {
+            return;
+        }
+        let Some(path) = Config::path() else {
+            return;
+        };
+
+        let text = fs::read_to_string(&path).unwrap_or_default();
+        let section = match sha1 {
+            // Reuses the rom's section as it's written, as hashes may be in upper case
+            Some(sha1) => {
+                let existing = Toml::parse(&text).ok().and_then(|table| {
+                    let roms = table.get("rom")?.as_table()?;
+                    roms.keys()
+                        .find(|key| key.eq_ignore_ascii_case(sha1))
+                        .cloned()
+                });
+                vec!["rom".to_string(), existing.unwrap_or(sha1.to_string())]
+            }
+            None => Vec::new(),
+        };
+        let result = Toml::set_value(&text, &section, key, &value).and_then(|text| {
+            fs::create_dir_all(path.parent().unwrap())
+                .and_then(|_| fs::write(&path, text))
+                .map_err(|e| e.to_string())
+        });
+        if let Err(e) = result {
+            println!("Failed to save {} to {}: {}", key, path.display(), e);
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// CRC-32, the checksum BPS patches use. Computed a bit at a time, as it's only run on roms.
+pub struct Crc32 {}
+
+impl Crc32 {
+    pub fn checksum(data: &[u8]) -> u32 {
+        let mut crc = 0xFFFFFFFF_u32;
+        for byte in data {
+            crc ^= *byte as u32;
+            for _ in 0..8 {
+                let mask = (crc & 1).wrapping_neg();
+                crc = (crc >> 1) ^ (0xEDB88320 & mask);
+            }
+        }
+        !crc
+    }
+}
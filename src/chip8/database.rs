@@ -0,0 +1,300 @@
+use super::analysis::Directions;
+use super::platform::{Platform, Quirks};
+use super::screen::Palette;
+use super::toml::{Toml, TomlTable, TomlValue};
+use super::utils::Utils;
+use serde_json::{Map, Value};
+use std::fs;
+use std::path::PathBuf;
+
+const EMBEDDED_DATABASE: &str = include_str!("rom_database.toml");
+// Octo's default for the largest program, anything above it needs XO-CHIP's memory
+const MAX_CHIP8_SIZE: f64 = 3584.0;
+
+// Everything known about a specific rom, anything missing falls back to the defaults
+#[derive(Clone, Debug, Default)]
+pub struct RomProfile {
+    pub title: Option<String>,
+    pub author: Option<String>,
+    pub year: Option<i64>,
+    pub platform: Option<Platform>,
+    pub quirks: Option<Quirks>,
+    pub speed: Option<u32>, // Instructions per frame
+    pub palette: Option<Palette>,
+    pub keys: Directions,
+}
+
+impl RomProfile {
+    pub fn from_table(table: &TomlTable) -> Result<Self, String> {
+        let mut profile = RomProfile::default();
+
+        for (name, value) in table {
+            let invalid = || format!("invalid value for \"{}\"", name);
+            match name.as_str() {
+                "title" => profile.title = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+                "author" => profile.author = Some(value.as_str().ok_or_else(invalid)?.to_string()),
+                "year" => profile.year = Some(value.as_integer().ok_or_else(invalid)?),
+                "platform" => {
+                    profile.platform = Some(
+                        value
+                            .as_str()
+                            .and_then(Platform::parse)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "speed" => {
+                    profile.speed = Some(
+                        value
+                            .as_integer()
+                            .and_then(|speed| u32::try_from(speed).ok())
+                            .filter(|speed| *speed > 0)
+                            .ok_or_else(invalid)?,
+                    )
+                }
+                "palette" => {
+                    let colors = value.as_array().ok_or_else(invalid)?;
+                    let colors: Vec<[u8; 3]> = colors
+                        .iter()
+                        .map(|color| color.as_str().and_then(Palette::parse_color))
+                        .collect::<Option<_>>()
+                        .ok_or_else(invalid)?;
+                    match colors[..] {
+                        [off, on] => profile.palette = Some(Palette { off, on }),
+                        _ => return Err("palette needs exactly two colors".to_string()),
+                    }
+                }
+                "quirks" | "keys" => {}
+                _ => return Err(format!("unknown field \"{}\"", name)),
+            }
+        }
+
+        let quirks_table = table.get("quirks").map(|value| value.as_table());
+        if profile.platform.is_some() || quirks_table.is_some() {
+            let mut quirks = profile
+                .platform
+                .map_or(Quirks::default(), Quirks::for_platform);
+            if let Some(quirks_table) = quirks_table {
+                quirks.apply_table(quirks_table.ok_or("quirks must be a table")?)?;
+            }
+            profile.quirks = Some(quirks);
+        }
+
+        if let Some(keys) = table.get("keys") {
+            for (direction, key) in keys.as_table().ok_or("keys must be a table")? {
+                let key = key
+                    .as_str()
+                    .and_then(|key| usize::from_str_radix(key, 16).ok())
+                    .filter(|key| *key <= 0xF)
+                    .ok_or(format!("\"{}\" must be a CHIP-8 key (0-F)", direction))?;
+                match direction.as_str() {
+                    "up" => profile.keys.up = Some(key),
+                    "down" => profile.keys.down = Some(key),
+                    "left" => profile.keys.left = Some(key),
+                    "right" => profile.keys.right = Some(key),
+                    "fire" => profile.keys.fire = Some(key),
+                    _ => return Err(format!("unknown direction \"{}\"", direction)),
+                }
+            }
+        }
+
+        Ok(profile)
+    }
+
+    // Octo's options, as saved in cartridges and the chip8Archive. Its quirk options are named
+    // for the departures from the VIP's behaviour.
+    pub fn from_octo_options(options: &Map<String, Value>) -> Self {
+        // Older versions of Octo saved some options as strings
+        let number = |name: &str| {
+            let value = options.get(name)?;
+            value.as_f64().or_else(|| value.as_str()?.parse().ok())
+        };
+        let flag = |name: &str| {
+            options
+                .get(name)
+                .and_then(|value| value.as_bool().or_else(|| Some(value.as_str()? == "true")))
+                .unwrap_or(false)
+        };
+        let color = |name: &str| options.get(name)?.as_str().and_then(Palette::parse_color);
+
+        RomProfile {
+            platform: number("maxSize")
+                .filter(|size| *size > MAX_CHIP8_SIZE)
+                .map(|_| Platform::XoChip),
+            quirks: Some(Quirks {
+                vf_reset: flag("logicQuirks"),
+                memory_increment: !flag("loadStoreQuirks"),
+                shift_vx: flag("shiftQuirks"),
+                jump_vx: flag("jumpQuirks"),
+                wrap_sprites: !flag("clipQuirks"),
+                key_press: false,
+            }),
+            speed: number("tickrate")
+                .filter(|tickrate| *tickrate >= 1.0)
+                .map(|tickrate| tickrate as u32),
+            palette: color("backgroundColor")
+                .zip(color("fillColor"))
+                .map(|(off, on)| Palette { off, on }),
+            ..Default::default()
+        }
+    }
+
+    // An entry of the chip8Archive's programs.json, its options along with the credits
+    pub fn from_archive_entry(entry: &Map<String, Value>) -> Self {
+        let text = |name: &str| entry.get(name)?.as_str().map(String::from);
+        let mut profile = entry
+            .get("options")
+            .and_then(Value::as_object)
+            .map(RomProfile::from_octo_options)
+            .unwrap_or_default();
+
+        profile.title = text("title");
+        profile.author = entry
+            .get("authors")
+            .and_then(Value::as_array)
+            .map(|authors| {
+                let names: Vec<&str> = authors.iter().filter_map(Value::as_str).collect();
+                names.join(", ")
+            })
+            .filter(|authors| !authors.is_empty())
+            .or_else(|| text("author"));
+        // Releases are dated like "2018-10-30"
+        profile.year = text("release").and_then(|date| date.get(..4)?.parse().ok());
+        if let Some(platform) = text("platform").as_deref().and_then(Platform::parse) {
+            profile.platform = Some(platform);
+        }
+        profile
+    }
+
+    // The fields that are set, in the same form from_table reads
+    pub fn to_table(&self) -> TomlTable {
+        let mut table = TomlTable::new();
+        let text = |value: &str| TomlValue::String(value.to_string());
+        let color = |color: [u8; 3]| {
+            TomlValue::String(format!("#{:02x}{:02x}{:02x}", color[0], color[1], color[2]))
+        };
+
+        if let Some(title) = &self.title {
+            table.insert("title".to_string(), text(title));
+        }
+        if let Some(author) = &self.author {
+            table.insert("author".to_string(), text(author));
+        }
+        if let Some(year) = self.year {
+            table.insert("year".to_string(), TomlValue::Integer(year));
+        }
+        if let Some(platform) = self.platform {
+            table.insert("platform".to_string(), text(platform.name()));
+        }
+        if let Some(speed) = self.speed {
+            table.insert("speed".to_string(), TomlValue::Integer(speed as i64));
+        }
+        if let Some(palette) = self.palette {
+            let colors = vec![color(palette.off), color(palette.on)];
+            table.insert("palette".to_string(), TomlValue::Array(colors));
+        }
+        if let Some(quirks) = self.quirks {
+            let quirks = quirks
+                .values()
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), TomlValue::Boolean(value)))
+                .collect();
+            table.insert("quirks".to_string(), TomlValue::Table(quirks));
+        }
+
+        let keys: TomlTable = [
+            ("up", self.keys.up),
+            ("down", self.keys.down),
+            ("left", self.keys.left),
+            ("right", self.keys.right),
+            ("fire", self.keys.fire),
+        ]
+        .into_iter()
+        .filter_map(|(direction, key)| Some((direction.to_string(), text(&format!("{:X}", key?)))))
+        .collect();
+        if !keys.is_empty() {
+            table.insert("keys".to_string(), TomlValue::Table(keys));
+        }
+        table
+    }
+
+    // Formatted like the rom file names, e.g. "Brix [Andreas Gustafsson, 1990]"
+    pub fn display_name(&self) -> Option<String> {
+        let title = self.title.as_ref()?;
+        let credits: Vec<String> = [self.author.clone(), self.year.map(|year| year.to_string())]
+            .into_iter()
+            .flatten()
+            .collect();
+
+        if credits.is_empty() {
+            Some(title.clone())
+        } else {
+            Some(format!("{} [{}]", title, credits.join(", ")))
+        }
+    }
+}
+
+pub struct RomDatabase {
+    entries: TomlTable,
+}
+
+impl RomDatabase {
+    // The database built into the binary, with the user's roms.toml from the config
+    // directory merged on top so it can correct or add entries
+    pub fn load() -> Self {
+        let mut entries =
+            Toml::parse(EMBEDDED_DATABASE).expect("Failed to parse built-in rom database");
+
+        if let Some(path) = RomDatabase::user_path() {
+            if let Ok(text) = fs::read_to_string(&path) {
+                match Toml::parse(&text) {
+                    Ok(overrides) => Toml::merge(&mut entries, overrides),
+                    Err(e) => println!("Ignoring rom database {}: {}", path.display(), e),
+                }
+            }
+        }
+
+        Self { entries }
+    }
+
+    pub fn lookup(&self, sha1: &str) -> Option<RomProfile> {
+        let table = self.entries.get(sha1)?.as_table()?;
+        match RomProfile::from_table(table) {
+            Ok(profile) => Some(profile),
+            Err(e) => {
+                println!("Ignoring invalid rom profile {}: {}", sha1, e);
+                None
+            }
+        }
+    }
+
+    pub fn user_path() -> Option<PathBuf> {
+        Utils::config_dir().map(|dir| dir.join("roms.toml"))
+    }
+
+    // Writes profiles into the user's roms.toml, replacing the fields they set and keeping
+    // everything else in the file as it was
+    pub fn save(profiles: &[(String, RomProfile)]) -> Result<PathBuf, String> {
+        let path = RomDatabase::user_path().ok_or("couldn't find the config directory")?;
+        let mut text = fs::read_to_string(&path).unwrap_or_default();
+
+        for (sha1, profile) in profiles {
+            let section = [sha1.clone()];
+            for (key, value) in profile.to_table() {
+                match value {
+                    TomlValue::Table(table) => {
+                        let section = [sha1.clone(), key];
+                        for (key, value) in table {
+                            text = Toml::set_value(&text, &section, &key, &value)?;
+                        }
+                    }
+                    value => text = Toml::set_value(&text, &section, &key, &value)?,
+                }
+            }
+        }
+
+        fs::create_dir_all(path.parent().unwrap())
+            .and_then(|_| fs::write(&path, text))
+            .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+        Ok(path)
+    }
+}
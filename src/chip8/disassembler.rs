@@ -0,0 +1,93 @@
+use super::analysis::Analyzer;
+
+// Turns opcodes back into assembly, using the mnemonics from Cowgod's Chip-8 technical reference
+pub struct Disassembler {}
+
+impl Disassembler {
+    pub fn disassemble(opcode: u16) -> String {
+        let nibbles = [
+            opcode >> 12,
+            (opcode >> 8) & 0xF,
+            (opcode >> 4) & 0xF,
+            opcode & 0xF,
+        ];
+        let x = nibbles[1];
+        let y = nibbles[2];
+        let n = nibbles[3];
+        let kk = opcode & 0xFF;
+        let nnn = opcode & 0xFFF;
+
+        match nibbles {
+            [0x0, 0x0, 0xE, 0x0] => "CLS".to_string(),
+            [0x0, 0x0, 0xE, 0xE] => "RET".to_string(),
+            [0x0, ..] => format!("SYS 0x{:03X}", nnn),
+            [0x1, ..] => format!("JP 0x{:03X}", nnn),
+            [0x2, ..] => format!("CALL 0x{:03X}", nnn),
+            [0x3, ..] => format!("SE V{:X}, 0x{:02X}", x, kk),
+            [0x4, ..] => format!("SNE V{:X}, 0x{:02X}", x, kk),
+            [0x5, _, _, 0x0] => format!("SE V{:X}, V{:X}", x, y),
+            [0x6, ..] => format!("LD V{:X}, 0x{:02X}", x, kk),
+            [0x7, ..] => format!("ADD V{:X}, 0x{:02X}", x, kk),
+            [0x8, _, _, 0x0] => format!("LD V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x1] => format!("OR V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x2] => format!("AND V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x3] => format!("XOR V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x4] => format!("ADD V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x5] => format!("SUB V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x6] => format!("SHR V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0x7] => format!("SUBN V{:X}, V{:X}", x, y),
+            [0x8, _, _, 0xE] => format!("SHL V{:X}, V{:X}", x, y),
+            [0x9, _, _, 0x0] => format!("SNE V{:X}, V{:X}", x, y),
+            [0xA, ..] => format!("LD I, 0x{:03X}", nnn),
+            [0xB, ..] => format!("JP V0, 0x{:03X}", nnn),
+            [0xC, ..] => format!("RND V{:X}, 0x{:02X}", x, kk),
+            [0xD, ..] => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            [0xE, _, 0x9, 0xE] => format!("SKP V{:X}", x),
+            [0xE, _, 0xA, 0x1] => format!("SKNP V{:X}", x),
+            [0xF, _, 0x0, 0x7] => format!("LD V{:X}, DT", x),
+            [0xF, _, 0x0, 0xA] => format!("LD V{:X}, K", x),
+            [0xF, _, 0x1, 0x5] => format!("LD DT, V{:X}", x),
+            [0xF, _, 0x1, 0x8] => format!("LD ST, V{:X}", x),
+            [0xF, _, 0x1, 0xE] => format!("ADD I, V{:X}", x),
+            [0xF, _, 0x2, 0x9] => format!("LD F, V{:X}", x),
+            [0xF, _, 0x3, 0x3] => format!("LD B, V{:X}", x),
+            [0xF, _, 0x5, 0x5] => format!("LD [I], V{:X}", x),
+            [0xF, _, 0x6, 0x5] => format!("LD V{:X}, [I]", x),
+            _ => format!("DW 0x{:04X}", opcode),
+        }
+    }
+
+    // One line per instruction from start to end, with bytes that are never reached by the
+    // code shown as data
+    pub fn listing(memory: &[u8], start: usize, end: usize) -> Vec<String> {
+        let reachable = Analyzer::reachable(memory, start);
+        let mut lines = Vec::new();
+
+        let mut address = start;
+        while address < end {
+            if reachable[address] && address + 1 < end {
+                let opcode = (memory[address] as u16) << 8 | memory[address + 1] as u16;
+                lines.push(format!(
+                    "{:03X}  {:04X}  {}",
+                    address,
+                    opcode,
+                    Disassembler::disassemble(opcode)
+                ));
+                address += 2;
+            } else {
+                // Data is usually sprites, so the bits are drawn out as well
+                let byte = memory[address];
+                let bits: String = (0..8)
+                    .map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' })
+                    .collect();
+                lines.push(format!(
+                    "{:03X}  {:02X}    DB 0x{:02X}  ; {}",
+                    address, byte, byte, bits
+                ));
+                address += 1;
+            }
+        }
+
+        lines
+    }
+}
@@ -0,0 +1,66 @@
+use super::overlay::{Overlay, COLUMNS, DIM_COLOR, HIGHLIGHT_COLOR, ROWS, TEXT_COLOR};
+
+// Rows taken by the title and the help line, the rest show the document
+const TEXT_ROWS: usize = ROWS - 4;
+
+/// A scrollable view of the .txt documentation that comes with a rom.
+pub struct DocViewer {
+    pub title: String,
+    pub text: String,
+    lines: Vec<String>,
+    scroll: usize,
+}
+
+impl DocViewer {
+    pub fn new(title: &str, text: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            text: text.to_string(),
+            lines: Overlay::wrap_text(text.trim_end(), COLUMNS - 2),
+            scroll: 0,
+        }
+    }
+
+    // Scrolls by the given number of lines, stopping once the last line is on screen
+    pub fn scroll(&mut self, offset: isize) {
+        let last = self.lines.len().saturating_sub(TEXT_ROWS) as isize;
+        self.scroll = (self.scroll as isize + offset).clamp(0, last) as usize;
+    }
+
+    pub fn page_size() -> isize {
+        TEXT_ROWS as isize
+    }
+
+    pub fn lines(&self) -> Vec<(String, [u8; 4])> {
+        let mut lines = vec![
+            (self.title.clone(), HIGHLIGHT_COLOR),
+            (String::new(), TEXT_COLOR),
+        ];
+
+        for line in self.lines.iter().skip(self.scroll).take(TEXT_ROWS) {
+            lines.push((line.clone(), TEXT_COLOR));
+        }
+        while lines.len() < ROWS - 1 {
+            lines.push((String::new(), TEXT_COLOR));
+        }
+
+        let last_shown = (self.scroll + TEXT_ROWS).min(self.lines.len());
+        lines.push((
+            format!(
+                "Lines {}-{} of {}, Up/Down/PgUp/PgDn to scroll, F2 or Esc to close",
+                self.scroll + 1,
+                last_shown,
+                self.lines.len()
+            ),
+            DIM_COLOR,
+        ));
+
+        lines
+    }
+
+    // For frontends without a window, the document is simply printed
+    pub fn print(&self) {
+        println!("{}\n", self.title);
+        println!("{}", self.text.trim_end());
+    }
+}
@@ -1,18 +1,25 @@
-use super::constants::FONT_START;
+use super::cdp1802::Cdp1802;
+use super::color_board::{BLOCK_HEIGHT, ZONE_WIDTH};
+use super::constants::{FONT_START, HIRES_HEIGHT, REAL_WIDTH};
+use super::memory_map::MemoryMap;
 use super::processor::*;
+use super::timing::{Timing, VIP_CYCLES_PER_FRAME};
 use super::utils::*;
+use super::vip::{VipBus, VipIo};
 use rand::Rng;
-const REAL_WIDTH: usize = 64;
-const REAL_HEIGHT: usize = 32;
-const BUFFER_CHUNK_SIZE: usize = 4;
 
-#[derive(PartialEq, Clone, Copy)]
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum PixelState {
     Off,
     On,
 }
 use PixelState::*;
 
+// Machine code gets a second of the VIP's time to return before it's taken to be stuck
+const MAX_MACHINE_CYCLES: u32 = VIP_CYCLES_PER_FRAME * 60;
+// CHIP-8X's VP-595 sound board divides this down by the value FXF8 sends it, plus one
+const TONE_BASE_FREQUENCY: f32 = 27535.0;
+
 pub struct InstructionHandler {}
 
 impl InstructionHandler {
@@ -26,12 +33,25 @@ impl InstructionHandler {
             '0' => match &nibbles[1..] {
                 ['0', 'E', '0'] => InstructionHandler::clear_screen(processor),
                 ['0', 'E', 'E'] => InstructionHandler::sub_return(processor),
-                _ => panic!("Instruction not recognized: {:?}", nibbles),
+                // The hires variant's clear, which is machine code at 0x230 anywhere else
+                ['2', '3', '0'] if processor.display_height() == HIRES_HEIGHT => {
+                    InstructionHandler::clear_screen(processor)
+                }
+                ['2', 'A', '0'] if processor.color_board.is_some() => {
+                    InstructionHandler::cycle_background(processor)
+                }
+                _ if processor.platform.runs_machine_code() => {
+                    InstructionHandler::machine_code(processor, nibbles)
+                }
+                _ => InstructionHandler::unknown_instruction(processor, nibbles),
             },
             '1' => InstructionHandler::jump(processor, nibbles),
             '2' => InstructionHandler::sub_call(processor, nibbles),
             '3' => InstructionHandler::skip_immediate_equal(processor, nibbles),
             '4' => InstructionHandler::skip_immediate_not_equal(processor, nibbles),
+            '5' if nibbles[3] == '1' && processor.color_board.is_some() => {
+                InstructionHandler::add_nibbles(processor, nibbles)
+            }
             '5' => InstructionHandler::skip_registers_equal(processor, nibbles),
             '6' => InstructionHandler::set_register_immediate(processor, nibbles),
             '7' => InstructionHandler::add_immediate(processor, nibbles),
@@ -45,17 +65,27 @@ impl InstructionHandler {
                 '6' => InstructionHandler::shift_right(processor, nibbles),
                 '7' => InstructionHandler::subtraction_reversed(processor, nibbles),
                 'E' => InstructionHandler::shift_left(processor, nibbles),
-                _ => panic!("Instruction not recognized: {:?}", nibbles),
+                _ => InstructionHandler::unknown_instruction(processor, nibbles),
             },
             '9' => InstructionHandler::skip_registers_not_equal(processor, nibbles),
             'A' => InstructionHandler::set_index(processor, nibbles),
+            // CHIP-8X gives up BNNN for colouring the screen
+            'B' if processor.color_board.is_some() => {
+                InstructionHandler::set_foreground(processor, nibbles)
+            }
             'B' => InstructionHandler::jump_with_offset(processor, nibbles),
             'C' => InstructionHandler::random(processor, nibbles),
             'D' => InstructionHandler::draw_sprite(processor, nibbles),
             'E' => match &nibbles[2..] {
                 ['9', 'E'] => InstructionHandler::skip_if_pressed(processor, nibbles),
                 ['A', '1'] => InstructionHandler::skip_if_not_pressed(processor, nibbles),
-                _ => panic!("Instruction not recognized: {:?}", nibbles),
+                ['F', '2'] if processor.color_board.is_some() => {
+                    InstructionHandler::skip_on_second_keypad(processor, nibbles, true)
+                }
+                ['F', '5'] if processor.color_board.is_some() => {
+                    InstructionHandler::skip_on_second_keypad(processor, nibbles, false)
+                }
+                _ => InstructionHandler::unknown_instruction(processor, nibbles),
             },
             'F' => match &nibbles[2..] {
                 ['0', '7'] => InstructionHandler::get_delay_timer(processor, nibbles),
@@ -67,18 +97,44 @@ impl InstructionHandler {
                 ['3', '3'] => InstructionHandler::decimal_store(processor, nibbles),
                 ['5', '5'] => InstructionHandler::store_memory(processor, nibbles),
                 ['6', '5'] => InstructionHandler::load_memory(processor, nibbles),
-                _ => panic!("Instruction not recognized: {:?}", nibbles),
+                ['F', '8'] if processor.color_board.is_some() => {
+                    InstructionHandler::set_tone(processor, nibbles)
+                }
+                ['F', 'B'] if processor.color_board.is_some() => {
+                    InstructionHandler::read_input_port(processor, nibbles)
+                }
+                _ => InstructionHandler::unknown_instruction(processor, nibbles),
             },
-            _ => panic!("Instruction not recognized: {:?}", nibbles),
+            _ => InstructionHandler::unknown_instruction(processor, nibbles),
         }
     }
 
+    // Stops the rom at an instruction this interpreter doesn't know, rather than guessing
+    fn unknown_instruction(processor: &mut Processor, nibbles: [char; 4]) {
+        processor.halt = Some(Halt::Error(ProcessorError::UnknownInstruction {
+            pc: processor.PC,
+            opcode: Utils::resolve_hex(&nibbles),
+        }));
+    }
+
+    // The keypad only has keys 0-F
+    fn invalid_key(processor: &mut Processor, key: u8) {
+        processor.halt = Some(Halt::Error(ProcessorError::InvalidKey {
+            pc: processor.PC,
+            key,
+        }));
+    }
+
     fn load_memory(processor: &mut Processor, nibbles: [char; 4]) {
         let register_x = Utils::resolve_hex(&[nibbles[1]]);
 
         for i in 0..=register_x {
             processor.V_REGS[i as usize] = processor.memory[(processor.I + i) as usize];
         }
+
+        if processor.quirks.memory_increment {
+            processor.I += register_x + 1;
+        }
     }
 
     fn store_memory(processor: &mut Processor, nibbles: [char; 4]) {
@@ -87,6 +143,10 @@ impl InstructionHandler {
         for i in 0..=register_x {
             processor.memory[(processor.I + i) as usize] = processor.V_REGS[i as usize];
         }
+
+        if processor.quirks.memory_increment {
+            processor.I += register_x + 1;
+        }
     }
 
     fn decimal_store(processor: &mut Processor, nibbles: [char; 4]) {
@@ -112,20 +172,12 @@ impl InstructionHandler {
         processor.I = (FONT_START as u16) + ((character * 5) as u16);
     }
 
+    // Halts until a key is pressed and released, which the processor checks on every frame
     fn get_key(processor: &mut Processor, nibbles: [char; 4]) {
-        let mut key = None;
-        for (ind, i) in processor.pressed_keys.lock().unwrap().iter().enumerate() {
-            if *i {
-                key = Some(ind);
-            }
-        }
-
-        if key.is_none() {
-            processor.PC -= 2;
-        } else {
-            let register_x = Utils::resolve_hex(&[nibbles[1]]);
-            processor.V_REGS[register_x as usize] = key.unwrap() as u8;
-        }
+        let register_x = Utils::resolve_hex(&[nibbles[1]]);
+        processor.halt = Some(Halt::WaitingForPress {
+            register: register_x as usize,
+        });
     }
 
     fn add_to_index(processor: &mut Processor, nibbles: [char; 4]) {
@@ -160,11 +212,10 @@ impl InstructionHandler {
         let register_x = Utils::resolve_hex(&[nibbles[1]]);
         let value_x = processor.V_REGS[register_x as usize];
 
-        assert!(
-            value_x <= 0xF,
-            "Invalid key on skip_if_not_pressed(): {}",
-            value_x
-        );
+        if value_x > 0xF {
+            InstructionHandler::invalid_key(processor, value_x);
+            return;
+        }
 
         if !processor.pressed_keys.lock().unwrap()[value_x as usize] {
             processor.PC += 2;
@@ -175,22 +226,104 @@ impl InstructionHandler {
         let register_x = Utils::resolve_hex(&[nibbles[1]]);
         let value_x = processor.V_REGS[register_x as usize];
 
-        assert!(
-            value_x <= 0xF,
-            "Invalid key on skip_if_pressed(): {}",
-            value_x
-        );
+        if value_x > 0xF {
+            InstructionHandler::invalid_key(processor, value_x);
+            return;
+        }
 
         if processor.pressed_keys.lock().unwrap()[value_x as usize] {
             processor.PC += 2;
         }
     }
 
+    // EXF2 skips if key VX is held on CHIP-8X's second keypad, EXF5 if it isn't
+    fn skip_on_second_keypad(processor: &mut Processor, nibbles: [char; 4], held: bool) {
+        let register_x = Utils::resolve_hex(&[nibbles[1]]);
+        let value_x = processor.V_REGS[register_x as usize];
+
+        if value_x > 0xF {
+            InstructionHandler::invalid_key(processor, value_x);
+            return;
+        }
+
+        if processor.second_keys.lock().unwrap()[value_x as usize] == held {
+            processor.PC += 2;
+        }
+    }
+
+    // FXF8 sets the pitch of the tone the sound timer plays
+    fn set_tone(processor: &mut Processor, nibbles: [char; 4]) {
+        let register_x = Utils::resolve_hex(&[nibbles[1]]);
+        let value_x = processor.V_REGS[register_x as usize];
+        processor.tone_frequency = Some(TONE_BASE_FREQUENCY / (value_x as f32 + 1.0));
+    }
+
+    // FXFB reads the VIP's input port into VX. Nothing is plugged into it here, so it reads 0.
+    fn read_input_port(processor: &mut Processor, nibbles: [char; 4]) {
+        let register_x = Utils::resolve_hex(&[nibbles[1]]);
+        processor.V_REGS[register_x as usize] = 0;
+    }
+
+    // 5XY1 adds VY to VX a nibble at a time, without carrying from one into the other, which
+    // suits the pairs of 4 bit coordinates BXY0 takes
+    fn add_nibbles(processor: &mut Processor, nibbles: [char; 4]) {
+        let register_x = Utils::resolve_hex(&[nibbles[1]]) as usize;
+        let register_y = Utils::resolve_hex(&[nibbles[2]]) as usize;
+        let value_x = processor.V_REGS[register_x];
+        let value_y = processor.V_REGS[register_y];
+        let high = (value_x >> 4).wrapping_add(value_y >> 4) & 0xF;
+        let low = (value_x & 0xF).wrapping_add(value_y & 0xF) & 0xF;
+        processor.V_REGS[register_x] = high << 4 | low;
+    }
+
+    fn cycle_background(processor: &mut Processor) {
+        if let Some(color_board) = &mut processor.color_board {
+            color_board.cycle_background();
+        }
+
+        processor
+            .render()
+            .expect("Failed to render pixel buffer on background change");
+    }
+
+    // BXY0 colours blocks of 8x4 pixels. VX's high nibble is the leftmost column of blocks and
+    // its low nibble how many more columns to its right, and V(X+1) gives the rows the same
+    // way. BXYN colours N rows of the 8 pixel wide zone at VX, V(X+1). The colour is VY.
+    fn set_foreground(processor: &mut Processor, nibbles: [char; 4]) {
+        let register_x = Utils::resolve_hex(&[nibbles[1]]) as usize;
+        let register_y = Utils::resolve_hex(&[nibbles[2]]);
+        let height = Utils::resolve_hex(&[nibbles[3]]) as usize;
+        let value_x = processor.V_REGS[register_x] as usize;
+        let value_x1 = processor.V_REGS[(register_x + 1) % 16] as usize;
+        let color = processor.V_REGS[register_y as usize];
+
+        let (columns, rows) = if height == 0 {
+            (
+                (value_x >> 4, (value_x & 0xF) + 1),
+                (
+                    (value_x1 >> 4) * BLOCK_HEIGHT,
+                    ((value_x1 & 0xF) + 1) * BLOCK_HEIGHT,
+                ),
+            )
+        } else {
+            (
+                ((value_x % REAL_WIDTH) / ZONE_WIDTH, 1),
+                (value_x1 % processor.display_height(), height),
+            )
+        };
+        if let Some(color_board) = &mut processor.color_board {
+            color_board.set_zones(columns, rows, color);
+        }
+
+        processor
+            .render()
+            .expect("Failed to render pixel buffer on colour change");
+    }
+
     fn random(processor: &mut Processor, nibbles: [char; 4]) {
         let register_x = Utils::resolve_hex(&[nibbles[1]]);
         let mask = Utils::resolve_hex(&nibbles[2..4]) as u8;
-        let mut rand_gen = rand::thread_rng();
-        processor.V_REGS[register_x as usize] = rand_gen.gen::<u8>() & mask;
+        processor.V_REGS[register_x as usize] = processor.rng.gen::<u8>() & mask;
     }
 
     fn jump_with_offset(processor: &mut Processor, nibbles: [char; 4]) {
@@ -199,24 +332,38 @@ impl InstructionHandler {
         address.push(nibbles[3]);
         let mut address =
             usize::from_str_radix(&address, 16).expect("Failed to resolve jump address");
-        address += processor.V_REGS[0] as usize;
+        // BXNN on the CHIP-48 and SCHIP, which adds VX instead of V0
+        let register = if processor.quirks.jump_vx {
+            Utils::resolve_hex(&[nibbles[1]])
+        } else {
+            0
+        };
+        address += processor.V_REGS[register as usize] as usize;
         processor.PC = address;
     }
 
     fn shift_left(processor: &mut Processor, nibbles: [char; 4]) {
         let register_x = Utils::resolve_hex(&[nibbles[1]]);
-        let register_y = Utils::resolve_hex(&[nibbles[2]]);
+        let register_y = if processor.quirks.shift_vx {
+            register_x
+        } else {
+            Utils::resolve_hex(&[nibbles[2]])
+        };
         let value_y = processor.V_REGS[register_y as usize];
-        processor.V_REGS[0xF] = (value_y & 128) >> 7;
         processor.V_REGS[register_x as usize] = value_y << 1;
+        processor.V_REGS[0xF] = (value_y & 128) >> 7;
     }
 
     fn shift_right(processor: &mut Processor, nibbles: [char; 4]) {
         let register_x = Utils::resolve_hex(&[nibbles[1]]);
-        let register_y = Utils::resolve_hex(&[nibbles[2]]);
+        let register_y = if processor.quirks.shift_vx {
+            register_x
+        } else {
+            Utils::resolve_hex(&[nibbles[2]])
+        };
         let value_y = processor.V_REGS[register_y as usize];
-        processor.V_REGS[0xF] = value_y & 1;
         processor.V_REGS[register_x as usize] = value_y >> 1;
+        processor.V_REGS[0xF] = value_y & 1;
     }
 
     fn subtraction_reversed(processor: &mut Processor, nibbles: [char; 4]) {
@@ -264,6 +411,9 @@ impl InstructionHandler {
         let value_x = processor.V_REGS[register_x as usize];
         let value_y = processor.V_REGS[register_y as usize];
         processor.V_REGS[register_x as usize] = value_x ^ value_y;
+        if processor.quirks.vf_reset {
+            processor.V_REGS[0xF] = 0;
+        }
     }
 
     fn and(processor: &mut Processor, nibbles: [char; 4]) {
@@ -272,6 +422,9 @@ impl InstructionHandler {
         let value_x = processor.V_REGS[register_x as usize];
         let value_y = processor.V_REGS[register_y as usize];
         processor.V_REGS[register_x as usize] = value_x & value_y;
+        if processor.quirks.vf_reset {
+            processor.V_REGS[0xF] = 0;
+        }
     }
 
     fn or(processor: &mut Processor, nibbles: [char; 4]) {
@@ -280,6 +433,9 @@ impl InstructionHandler {
         let value_x = processor.V_REGS[register_x as usize];
         let value_y = processor.V_REGS[register_y as usize];
         processor.V_REGS[register_x as usize] = value_x | value_y;
+        if processor.quirks.vf_reset {
+            processor.V_REGS[0xF] = 0;
+        }
     }
 
     fn set_register_register(processor: &mut Processor, nibbles: [char; 4]) {
@@ -326,23 +482,123 @@ impl InstructionHandler {
     }
 
     fn sub_call(processor: &mut Processor, nibbles: [char; 4]) {
-        let mut address = ['0'; 3];
-        let address_chars: &[char] = &format!("{:0>3X}", processor.PC)
-            .chars()
-            .collect::<Vec<_>>()
-            .into_boxed_slice();
-        address.copy_from_slice(address_chars);
-        processor.stack.push(address);
+        if processor.SP == processor.stack.len() {
+            processor.halt = Some(Halt::Error(ProcessorError::StackOverflow {
+                pc: processor.PC,
+                depth: processor.stack.len(),
+            }));
+            return;
+        }
+        processor.set_stack_entry(processor.SP, processor.PC as u16);
+        processor.SP += 1;
 
         let jump_address = Utils::resolve_hex(&nibbles[1..]);
         processor.PC = jump_address as usize;
     }
 
+    // Returns to the call instruction itself, which is then stepped over like any other
     fn sub_return(processor: &mut Processor) {
-        let return_addr = processor.stack.pop().expect("Stack empty on return");
-        let mut jump_addr = ['0'; 4];
-        jump_addr[1..].copy_from_slice(&return_addr);
-        InstructionHandler::jump(processor, jump_addr);
+        if processor.SP == 0 {
+            processor.halt = Some(Halt::Error(ProcessorError::StackUnderflow {
+                pc: processor.PC,
+            }));
+            return;
+        }
+        processor.SP -= 1;
+        processor.PC = processor.stack_entry(processor.SP) as usize;
+    }
+
+    // Runs the 1802 machine code at NNN until it returns to the interpreter with SEP R4 (D4).
+    // Its registers are set up the way the VIP's interpreter left them: R3 is the program
+    // counter, R2 the stack, R5 the CHIP-8 program counter, R6 and R7 point at VX and VY,
+    // R8 holds the delay and sound timers, R9 a random number, RA is I and RB.1 the display
+    // page. V0-VF and the display are in memory at 0xEF0 and 0xF00 while it runs, or a page
+    // lower with the hires variant's two page display. Without the VIP's memory map that's
+    // where the rom can be, so whatever was there is put back afterwards.
+    fn machine_code(processor: &mut Processor, nibbles: [char; 4]) {
+        let address = Utils::resolve_hex(&nibbles[1..]) as usize;
+        let register_x = Utils::resolve_hex(&[nibbles[1]]) as usize;
+        let register_y = Utils::resolve_hex(&[nibbles[2]]) as usize;
+
+        let layout = processor.vip_layout();
+        let reserved = layout.stack_start..layout.display_start + layout.display_size;
+        let saved = match processor.memory_map {
+            MemoryMap::Separate => Some(processor.memory[reserved.clone()].to_vec()),
+            MemoryMap::Vip => None,
+        };
+        processor.memory[layout.registers_start..layout.registers_start + 16]
+            .copy_from_slice(&processor.V_REGS);
+        if saved.is_some() {
+            processor.display_to_memory();
+        }
+
+        let mut cpu = Cdp1802 {
+            p: 3,
+            x: 2,
+            ie: true,
+            ..Default::default()
+        };
+        cpu.r[2] = layout.stack_end as u16;
+        cpu.r[3] = address as u16;
+        cpu.r[5] = processor.PC as u16 + 2;
+        cpu.r[6] = (layout.registers_start + register_x) as u16;
+        cpu.r[7] = (layout.registers_start + register_y) as u16;
+        cpu.r[8] = (processor.delay_timer as u16) << 8 | processor.sound_timer as u16;
+        cpu.r[9] = processor.rng.gen::<u8>() as u16;
+        cpu.r[0xA] = processor.I;
+        cpu.r[0xB] = layout.display_start as u16;
+
+        let mut io = VipIo::new(*processor.pressed_keys.lock().unwrap());
+        let mut bus = VipBus {
+            memory: &mut processor.memory,
+            io: &mut io,
+        };
+        // The 60Hz interrupt keeps counting the timers down while the machine code runs
+        let mut frame_cycles = match processor.timing {
+            Timing::Uniform => 0,
+            Timing::Vip => processor.frame_cycles,
+        };
+        let mut cycles = 0;
+        while cpu.p != 4 {
+            if cycles >= MAX_MACHINE_CYCLES {
+                processor.halt = Some(Halt::Error(ProcessorError::MachineCodeTimeout {
+                    pc: processor.PC,
+                    address,
+                }));
+                if let Some(saved) = saved {
+                    processor.memory[reserved].copy_from_slice(&saved);
+                }
+                return;
+            }
+            let used = cpu.step(&mut bus);
+            cycles += used;
+            frame_cycles += used;
+            if frame_cycles >= VIP_CYCLES_PER_FRAME {
+                frame_cycles -= VIP_CYCLES_PER_FRAME;
+                let [delay, sound] = cpu.r[8].to_be_bytes();
+                cpu.r[8] = u16::from_be_bytes([delay.saturating_sub(1), sound.saturating_sub(1)]);
+                cpu.idle = false;
+            }
+        }
+
+        processor.V_REGS.copy_from_slice(
+            &processor.memory[layout.registers_start..layout.registers_start + 16],
+        );
+        processor.I = cpu.r[0xA];
+        [processor.delay_timer, processor.sound_timer] = cpu.r[8].to_be_bytes();
+        // The instruction is stepped over afterwards like any other
+        processor.PC = (cpu.r[5] as usize).saturating_sub(2);
+        if processor.timing == Timing::Vip {
+            processor.frame_cycles = frame_cycles;
+        }
+
+        if let Some(saved) = saved {
+            processor.display_from_memory();
+            processor.memory[reserved].copy_from_slice(&saved);
+        }
+        processor
+            .render()
+            .expect("Failed to render pixel buffer after machine code");
     }
 
     fn set_register_immediate(processor: &mut Processor, nibbles: [char; 4]) {
@@ -376,7 +632,8 @@ impl InstructionHandler {
         let register_y = Utils::resolve_hex(&[nibbles[2]]);
         let height = Utils::resolve_hex(&[nibbles[3]]);
         let x = processor.V_REGS[register_x as usize] % (REAL_WIDTH as u8);
-        let y = processor.V_REGS[register_y as usize] % (REAL_HEIGHT as u8);
+        let display_height = processor.display_height();
+        let y = processor.V_REGS[register_y as usize] % (display_height as u8);
         let base_address = processor.I;
 
         processor.V_REGS[0xF] = 0;
@@ -389,11 +646,13 @@ impl InstructionHandler {
             for j in 0..8 {
                 let sprite_state = (sprite_byte & (128 >> j)) >> (7 - j);
 
-                let pixel_state = InstructionHandler::get_pixel(
-                    processor,
-                    (x + j) as usize,
-                    (y + (i as u8)) as usize,
-                );
+                let (mut pixel_x, mut pixel_y) = ((x + j) as usize, (y + (i as u8)) as usize);
+                if processor.quirks.wrap_sprites {
+                    pixel_x %= REAL_WIDTH;
+                    pixel_y %= display_height;
+                }
+
+                let pixel_state = InstructionHandler::get_pixel(processor, pixel_x, pixel_y);
                 if pixel_state.is_none() {
                     continue;
                 }
@@ -407,84 +666,65 @@ impl InstructionHandler {
                 } else {
                     Off
                 };
-                InstructionHandler::set_pixel(
-                    processor,
-                    (x + j) as usize,
-                    (y + (i as u8)) as usize,
-                    final_state,
-                );
+                InstructionHandler::set_pixel(processor, pixel_x, pixel_y, final_state);
             }
         }
 
         processor
-            .pixels
-            .as_mut()
-            .unwrap()
             .render()
             .expect("Failed to render pixel buffer on sprite draw");
     }
 
     pub fn get_pixel(processor: &mut Processor, x: usize, y: usize) -> Option<PixelState> {
-        if x >= REAL_WIDTH || y >= REAL_HEIGHT {
+        if x >= REAL_WIDTH || y >= processor.display_height() {
             //println!("NOTE: Attempting to fetch pixel beyond screen.");
             return None;
         }
 
-        let pixel = processor
-            .pixels
-            .as_ref()
-            .unwrap()
-            .frame()
-            .chunks_exact(BUFFER_CHUNK_SIZE)
-            .nth(y * REAL_WIDTH + x)
-            .unwrap();
-
-        if pixel[0] == 0xff {
-            Some(On)
-        } else {
-            Some(Off)
+        match processor.memory_map {
+            MemoryMap::Separate => Some(processor.display[y * REAL_WIDTH + x]),
+            MemoryMap::Vip => {
+                let at = processor.vip_layout().display_start + (y * REAL_WIDTH + x) / 8;
+                let byte = processor.memory[at];
+                Some(if byte & (0x80 >> (x % 8)) != 0 {
+                    On
+                } else {
+                    Off
+                })
+            }
         }
     }
 
     pub fn set_pixel(processor: &mut Processor, x: usize, y: usize, state: PixelState) {
-        if x >= REAL_WIDTH || y >= REAL_HEIGHT {
+        if x >= REAL_WIDTH || y >= processor.display_height() {
             //println!("NOTE: Attempting to set pixel beyond screen.");
             return;
         }
 
-        let pixel = processor
-            .pixels
-            .as_mut()
-            .unwrap()
-            .frame_mut()
-            .chunks_exact_mut(BUFFER_CHUNK_SIZE)
-            .nth(y * REAL_WIDTH + x)
-            .unwrap();
-        let color_state = state as u8;
-        pixel[0] = 0xff * color_state;
-        pixel[1] = 0xff * color_state;
-        pixel[2] = 0xff * color_state;
-        pixel[3] = 0xff;
+        match processor.memory_map {
+            MemoryMap::Separate => processor.display[y * REAL_WIDTH + x] = state,
+            MemoryMap::Vip => {
+                let at = processor.vip_layout().display_start + (y * REAL_WIDTH + x) / 8;
+                let byte = &mut processor.memory[at];
+                match state {
+                    On => *byte |= 0x80 >> (x % 8),
+                    Off => *byte &= !(0x80 >> (x % 8)),
+                }
+            }
+        }
     }
 
     pub fn clear_screen(processor: &mut Processor) {
-        for pixel in processor
-            .pixels
-            .as_mut()
-            .unwrap()
-            .frame_mut()
-            .chunks_exact_mut(BUFFER_CHUNK_SIZE)
-        {
-            pixel[0] = 0x00;
-            pixel[1] = 0x00;
-            pixel[2] = 0x00;
-            pixel[3] = 0xff;
+        match processor.memory_map {
+            MemoryMap::Separate => processor.display.fill(Off),
+            MemoryMap::Vip => {
+                let layout = processor.vip_layout();
+                processor.memory[layout.display_start..layout.display_start + layout.display_size]
+                    .fill(0)
+            }
         }
 
         processor
-            .pixels
-            .as_ref()
-            .unwrap()
             .render()
             .expect("Failed to render pixel buffer on screen clear");
     }
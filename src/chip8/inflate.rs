@@ -0,0 +1,286 @@
+// Decompresses DEFLATE data (RFC 1951), which is what zip archives use
+pub struct Inflate {}
+
+// Base lengths and extra bits for length codes 257 to 285
+const LENGTH_BASES: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA_BITS: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+// Base distances and extra bits for distance codes 0 to 29
+const DISTANCE_BASES: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA_BITS: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+// The order code length code lengths are stored in, in dynamic blocks
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize, // In bits
+}
+
+impl BitReader<'_> {
+    fn bits(&mut self, count: u8) -> Result<u32, String> {
+        let mut value = 0;
+        for i in 0..count {
+            let byte = self
+                .data
+                .get(self.position / 8)
+                .ok_or("compressed data ends early")?;
+            value |= (((byte >> (self.position % 8)) & 1) as u32) << i;
+            self.position += 1;
+        }
+        Ok(value)
+    }
+
+    fn align_to_byte(&mut self) {
+        self.position = self.position.div_ceil(8) * 8;
+    }
+}
+
+// A canonical Huffman code, stored as the number of codes of each length and the symbols
+// sorted by code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    fn new(lengths: &[u8]) -> Self {
+        let mut counts = [0; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+
+        let mut symbols: Vec<u16> = (0..lengths.len() as u16)
+            .filter(|symbol| lengths[*symbol as usize] != 0)
+            .collect();
+        symbols.sort_by_key(|symbol| lengths[*symbol as usize]);
+
+        Self { counts, symbols }
+    }
+
+    // Reads a code a bit at a time, as Huffman codes are packed starting from their top bit
+    fn decode(&self, reader: &mut BitReader) -> Result<u16, String> {
+        let mut code = 0;
+        let mut first = 0;
+        let mut index = 0;
+        for length in 1..16 {
+            code |= reader.bits(1)? as i32;
+            let count = self.counts[length] as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("invalid Huffman code".to_string())
+    }
+}
+
+impl Inflate {
+    // Gives up once the output grows past the limit, so a small archive can't expand into
+    // gigabytes before anything gets to check its size
+    pub fn decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, String> {
+        let mut reader = BitReader { data, position: 0 };
+        let mut output = Vec::new();
+
+        loop {
+            let last_block = reader.bits(1)? == 1;
+            match reader.bits(2)? {
+                0 => Inflate::stored_block(&mut reader, &mut output)?,
+                1 => {
+                    let (literals, distances) = Inflate::fixed_codes();
+                    Inflate::compressed_block(
+                        &mut reader,
+                        &mut output,
+                        &literals,
+                        &distances,
+                        limit,
+                    )?
+                }
+                2 => {
+                    let (literals, distances) = Inflate::dynamic_codes(&mut reader)?;
+                    Inflate::compressed_block(
+                        &mut reader,
+                        &mut output,
+                        &literals,
+                        &distances,
+                        limit,
+                    )?
+                }
+                _ => return Err("invalid block type".to_string()),
+            }
+            if output.len() > limit {
+                return Err(Inflate::too_large(limit));
+            }
+            if last_block {
+                return Ok(output);
+            }
+        }
+    }
+
+    fn stored_block(reader: &mut BitReader, output: &mut Vec<u8>) -> Result<(), String> {
+        reader.align_to_byte();
+        let length = reader.bits(16)?;
+        let complement = reader.bits(16)?;
+        if length != !complement & 0xFFFF {
+            return Err("corrupt stored block".to_string());
+        }
+
+        let start = reader.position / 8;
+        let bytes = reader
+            .data
+            .get(start..start + length as usize)
+            .ok_or("compressed data ends early")?;
+        output.extend_from_slice(bytes);
+        reader.position += length as usize * 8;
+        Ok(())
+    }
+
+    fn fixed_codes() -> (Huffman, Huffman) {
+        let mut lengths = [0; 288];
+        lengths[..144].fill(8);
+        lengths[144..256].fill(9);
+        lengths[256..280].fill(7);
+        lengths[280..].fill(8);
+        (Huffman::new(&lengths), Huffman::new(&[5; 30]))
+    }
+
+    fn dynamic_codes(reader: &mut BitReader) -> Result<(Huffman, Huffman), String> {
+        let literal_count = reader.bits(5)? as usize + 257;
+        let distance_count = reader.bits(5)? as usize + 1;
+        let code_length_count = reader.bits(4)? as usize + 4;
+
+        let mut code_lengths = [0; 19];
+        for index in CODE_LENGTH_ORDER.iter().take(code_length_count) {
+            code_lengths[*index] = reader.bits(3)? as u8;
+        }
+        let code_length_code = Huffman::new(&code_lengths);
+
+        // Both tables' lengths are coded as one run, with repeats that can cross between them
+        let mut lengths = Vec::with_capacity(literal_count + distance_count);
+        while lengths.len() < literal_count + distance_count {
+            let (length, repeat) = match code_length_code.decode(reader)? {
+                symbol @ 0..=15 => (symbol as u8, 1),
+                16 => (
+                    *lengths.last().ok_or("repeat with no previous length")?,
+                    3 + reader.bits(2)?,
+                ),
+                17 => (0, 3 + reader.bits(3)?),
+                _ => (0, 11 + reader.bits(7)?),
+            };
+            for _ in 0..repeat {
+                lengths.push(length);
+            }
+        }
+        if lengths.len() > literal_count + distance_count {
+            return Err("code lengths overrun".to_string());
+        }
+
+        Ok((
+            Huffman::new(&lengths[..literal_count]),
+            Huffman::new(&lengths[literal_count..]),
+        ))
+    }
+
+    fn compressed_block(
+        reader: &mut BitReader,
+        output: &mut Vec<u8>,
+        literals: &Huffman,
+        distances: &Huffman,
+        limit: usize,
+    ) -> Result<(), String> {
+        loop {
+            if output.len() > limit {
+                return Err(Inflate::too_large(limit));
+            }
+            let symbol = literals.decode(reader)? as usize;
+            match symbol {
+                0..=255 => output.push(symbol as u8),
+                256 => return Ok(()),
+                _ => {
+                    let index = symbol - 257;
+                    if index >= LENGTH_BASES.len() {
+                        return Err("invalid length code".to_string());
+                    }
+                    let length = LENGTH_BASES[index] as usize
+                        + reader.bits(LENGTH_EXTRA_BITS[index])? as usize;
+
+                    let index = distances.decode(reader)? as usize;
+                    if index >= DISTANCE_BASES.len() {
+                        return Err("invalid distance code".to_string());
+                    }
+                    let distance = DISTANCE_BASES[index] as usize
+                        + reader.bits(DISTANCE_EXTRA_BITS[index])? as usize;
+                    if distance > output.len() {
+                        return Err("distance reaches before the start".to_string());
+                    }
+
+                    // Copied a byte at a time, as the source can overlap what's being written
+                    let start = output.len() - distance;
+                    for i in 0..length {
+                        output.push(output[start + i]);
+                    }
+                }
+            }
+        }
+    }
+
+    fn too_large(limit: usize) -> String {
+        format!("decompresses to more than {} bytes", limit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stored_block() {
+        let data = [
+            0x01, 0x06, 0x00, 0xF9, 0xFF, b'C', b'H', b'I', b'P', b'-', b'8',
+        ];
+        assert_eq!(Inflate::decompress(&data, 0x100).unwrap(), b"CHIP-8");
+    }
+
+    #[test]
+    fn fixed_block_with_overlapping_copy() {
+        let data = [0x73, 0x74, 0x72, 0x84, 0x42, 0x00];
+        assert_eq!(Inflate::decompress(&data, 0x100).unwrap(), b"ABABABABAB");
+    }
+
+    #[test]
+    fn dynamic_block() {
+        let data = [
+            0x0D, 0xC1, 0x01, 0x01, 0x00, 0x00, 0x08, 0xC2, 0xB0, 0x6C, 0xE7, 0xD0, 0xBF, 0x92,
+            0x6E, 0x40, 0x2C, 0x2E, 0x93, 0xD5, 0x3C, 0x3B, 0x0E,
+        ];
+        assert_eq!(
+            Inflate::decompress(&data, 0x100).unwrap(),
+            b"AAABCDACEBECAEDCBBBBCDEA"
+        );
+    }
+
+    #[test]
+    fn stops_at_the_limit() {
+        let data = [0x73, 0x74, 0x72, 0x84, 0x42, 0x00];
+        assert!(Inflate::decompress(&data, 4).is_err());
+    }
+
+    #[test]
+    fn truncated_data() {
+        assert!(Inflate::decompress(&[0x73, 0x74], 0x100).is_err());
+    }
+}
@@ -0,0 +1,540 @@
+use super::analysis::{Directions, KeyUsage};
+use super::overlay::{DIM_COLOR, HIGHLIGHT_COLOR, TEXT_COLOR};
+use super::toml::{Toml, TomlTable, TomlValue};
+use super::utils::Utils;
+use std::fs;
+use std::path::Path;
+use winit::keyboard::{Key, KeyCode, NamedKey, SmolStr};
+use winit_input_helper::WinitInputHelper;
+
+// Original COSMAC VIP keypad looks like:
+// 1   2   3   C
+// 4   5   6   D
+// 7   8   9   E
+// A   0   B   F
+// This mapping is made such that given a key value 0-F,
+// you can easily get the corresponding keyboard keycode
+const DEFAULT_BINDINGS: [KeyCode; 16] = [
+    KeyCode::KeyX,
+    KeyCode::Digit1,
+    KeyCode::Digit2,
+    KeyCode::Digit3,
+    KeyCode::KeyQ,
+    KeyCode::KeyW,
+    KeyCode::KeyE,
+    KeyCode::KeyA,
+    KeyCode::KeyS,
+    KeyCode::KeyD,
+    KeyCode::KeyZ,
+    KeyCode::KeyC,
+    KeyCode::Digit4,
+    KeyCode::KeyR,
+    KeyCode::KeyF,
+    KeyCode::KeyV,
+];
+// CHIP-8X's second keypad, on the numpad in the same layout
+const DEFAULT_SECOND_BINDINGS: [KeyCode; 16] = [
+    KeyCode::NumpadDecimal,
+    KeyCode::Numpad7,
+    KeyCode::Numpad8,
+    KeyCode::Numpad9,
+    KeyCode::Numpad4,
+    KeyCode::Numpad5,
+    KeyCode::Numpad6,
+    KeyCode::Numpad1,
+    KeyCode::Numpad2,
+    KeyCode::Numpad3,
+    KeyCode::Numpad0,
+    KeyCode::NumpadEnter,
+    KeyCode::NumpadDivide,
+    KeyCode::NumpadMultiply,
+    KeyCode::NumpadSubtract,
+    KeyCode::NumpadAdd,
+];
+
+// Names used for physical keys in keymap files, matching winit's KeyCode variants
+const KEY_CODE_NAMES: [(&str, KeyCode); 77] = [
+    ("KeyA", KeyCode::KeyA),
+    ("KeyB", KeyCode::KeyB),
+    ("KeyC", KeyCode::KeyC),
+    ("KeyD", KeyCode::KeyD),
+    ("KeyE", KeyCode::KeyE),
+    ("KeyF", KeyCode::KeyF),
+    ("KeyG", KeyCode::KeyG),
+    ("KeyH", KeyCode::KeyH),
+    ("KeyI", KeyCode::KeyI),
+    ("KeyJ", KeyCode::KeyJ),
+    ("KeyK", KeyCode::KeyK),
+    ("KeyL", KeyCode::KeyL),
+    ("KeyM", KeyCode::KeyM),
+    ("KeyN", KeyCode::KeyN),
+    ("KeyO", KeyCode::KeyO),
+    ("KeyP", KeyCode::KeyP),
+    ("KeyQ", KeyCode::KeyQ),
+    ("KeyR", KeyCode::KeyR),
+    ("KeyS", KeyCode::KeyS),
+    ("KeyT", KeyCode::KeyT),
+    ("KeyU", KeyCode::KeyU),
+    ("KeyV", KeyCode::KeyV),
+    ("KeyW", KeyCode::KeyW),
+    ("KeyX", KeyCode::KeyX),
+    ("KeyY", KeyCode::KeyY),
+    ("KeyZ", KeyCode::KeyZ),
+    ("Digit0", KeyCode::Digit0),
+    ("Digit1", KeyCode::Digit1),
+    ("Digit2", KeyCode::Digit2),
+    ("Digit3", KeyCode::Digit3),
+    ("Digit4", KeyCode::Digit4),
+    ("Digit5", KeyCode::Digit5),
+    ("Digit6", KeyCode::Digit6),
+    ("Digit7", KeyCode::Digit7),
+    ("Digit8", KeyCode::Digit8),
+    ("Digit9", KeyCode::Digit9),
+    ("Numpad0", KeyCode::Numpad0),
+    ("Numpad1", KeyCode::Numpad1),
+    ("Numpad2", KeyCode::Numpad2),
+    ("Numpad3", KeyCode::Numpad3),
+    ("Numpad4", KeyCode::Numpad4),
+    ("Numpad5", KeyCode::Numpad5),
+    ("Numpad6", KeyCode::Numpad6),
+    ("Numpad7", KeyCode::Numpad7),
+    ("Numpad8", KeyCode::Numpad8),
+    ("Numpad9", KeyCode::Numpad9),
+    ("NumpadAdd", KeyCode::NumpadAdd),
+    ("NumpadSubtract", KeyCode::NumpadSubtract),
+    ("NumpadMultiply", KeyCode::NumpadMultiply),
+    ("NumpadDivide", KeyCode::NumpadDivide),
+    ("NumpadDecimal", KeyCode::NumpadDecimal),
+    ("NumpadEnter", KeyCode::NumpadEnter),
+    ("ArrowUp", KeyCode::ArrowUp),
+    ("ArrowDown", KeyCode::ArrowDown),
+    ("ArrowLeft", KeyCode::ArrowLeft),
+    ("ArrowRight", KeyCode::ArrowRight),
+    ("Space", KeyCode::Space),
+    ("Enter", KeyCode::Enter),
+    ("Backspace", KeyCode::Backspace),
+    ("ShiftLeft", KeyCode::ShiftLeft),
+    ("ShiftRight", KeyCode::ShiftRight),
+    ("ControlLeft", KeyCode::ControlLeft),
+    ("ControlRight", KeyCode::ControlRight),
+    ("AltLeft", KeyCode::AltLeft),
+    ("AltRight", KeyCode::AltRight),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash),
+    ("Semicolon", KeyCode::Semicolon),
+    ("Quote", KeyCode::Quote),
+    ("BracketLeft", KeyCode::BracketLeft),
+    ("BracketRight", KeyCode::BracketRight),
+    ("Backslash", KeyCode::Backslash),
+    ("Minus", KeyCode::Minus),
+    ("Equal", KeyCode::Equal),
+    ("Backquote", KeyCode::Backquote),
+    ("IntlBackslash", KeyCode::IntlBackslash),
+];
+
+// Named keys that can be bound in logical mode, everything else is a single character
+const NAMED_KEY_NAMES: [(&str, NamedKey); 10] = [
+    ("ArrowUp", NamedKey::ArrowUp),
+    ("ArrowDown", NamedKey::ArrowDown),
+    ("ArrowLeft", NamedKey::ArrowLeft),
+    ("ArrowRight", NamedKey::ArrowRight),
+    ("Space", NamedKey::Space),
+    ("Enter", NamedKey::Enter),
+    ("Backspace", NamedKey::Backspace),
+    ("Shift", NamedKey::Shift),
+    ("Control", NamedKey::Control),
+    ("Alt", NamedKey::Alt),
+];
+
+// Keys the emulator uses for its own controls, so they can't be bound to the keypad
+const HOTKEY_NAMES: [&str; 13] = [
+    "Tab", "Escape", "F1", "F2", "F3", "F4", "F5", "F6", "F7", "F8", "F9", "F10", "F11",
+];
+
+// Keys in the order they appear on the COSMAC VIP keypad
+const KEYPAD_LAYOUT: [[usize; 4]; 4] = [
+    [0x1, 0x2, 0x3, 0xC],
+    [0x4, 0x5, 0x6, 0xD],
+    [0x7, 0x8, 0x9, 0xE],
+    [0xA, 0x0, 0xB, 0xF],
+];
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HostKey {
+    Physical(KeyCode), // Key position on a US layout, regardless of what it types
+    Logical(Key),      // Whatever key produces this character on the current layout
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DirectionalKeys {
+    Arrows,
+    Wasd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    Physical,
+    Logical,
+}
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+    pub bindings: [Vec<HostKey>; 16],
+    pub second_bindings: [Vec<HostKey>; 16], // CHIP-8X's second keypad
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        Self {
+            bindings: DEFAULT_BINDINGS.map(|code| vec![HostKey::Physical(code)]),
+            second_bindings: DEFAULT_SECOND_BINDINGS.map(|code| vec![HostKey::Physical(code)]),
+        }
+    }
+}
+
+impl Keymap {
+    // Starts from the default bindings, then applies the user's keymap.toml from the
+    // config directory, any keymap tables from config.toml (global first, then the rom's)
+    // and finally a "<rom name>.keymap.toml" sitting next to the rom
+    pub fn load(rom_path: &str, config_tables: &[&TomlTable]) -> Self {
+        let mut keymap = Keymap::default();
+
+        if let Some(file) = Utils::config_dir().map(|dir| dir.join("keymap.toml")) {
+            keymap.apply_file_if_present(&file);
+        }
+        for table in config_tables {
+            if let Err(e) = keymap.apply_table(table) {
+                println!("Ignoring keymap in config.toml: {}", e);
+            }
+        }
+        keymap.apply_file_if_present(&Path::new(rom_path).with_extension("keymap.toml"));
+
+        keymap
+    }
+
+    fn apply_file_if_present(&mut self, file: &Path) {
+        if file.is_file() {
+            if let Err(e) = self.apply_file(file) {
+                println!("Ignoring keymap {}: {}", file.display(), e);
+            }
+        }
+    }
+
+    pub fn apply_file(&mut self, path: &Path) -> Result<(), String> {
+        let text = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let table = Toml::parse(&text).map_err(|e| e.to_string())?;
+        self.apply_table(&table)
+    }
+
+    // Keymap files look like:
+    //
+    // layout = "logical" # or "physical" (the default)
+    // [keys]
+    // 1 = ["1", "&"]
+    // A = "z"
+    // [keys2] # CHIP-8X's second keypad
+    // 5 = "Numpad5"
+    //
+    // Every CHIP-8 key listed replaces its default bindings, unlisted keys are left alone
+    pub fn apply_table(&mut self, table: &TomlTable) -> Result<(), String> {
+        let layout = match table.get("layout").map(|value| value.as_str()) {
+            None | Some(Some("physical")) => Layout::Physical,
+            Some(Some("logical")) => Layout::Logical,
+            _ => return Err("layout must be \"physical\" or \"logical\"".to_string()),
+        };
+
+        for (name, bindings) in [
+            ("keys", &mut self.bindings),
+            ("keys2", &mut self.second_bindings),
+        ] {
+            if let Some(keys) = table.get(name) {
+                let keys = keys.as_table().ok_or(format!("{} must be a table", name))?;
+                Keymap::apply_keys(bindings, keys, layout)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn apply_keys(
+        bindings: &mut [Vec<HostKey>; 16],
+        keys: &TomlTable,
+        layout: Layout,
+    ) -> Result<(), String> {
+        for (chip8_key, value) in keys {
+            let index = u8::from_str_radix(chip8_key, 16)
+                .ok()
+                .filter(|index| *index <= 0xF)
+                .ok_or(format!("\"{}\" is not a CHIP-8 key (0-F)", chip8_key))?;
+
+            let names = match value {
+                TomlValue::String(name) => vec![name.as_str()],
+                TomlValue::Array(values) => values
+                    .iter()
+                    .map(|value| value.as_str().ok_or("key names must be strings"))
+                    .collect::<Result<_, _>>()?,
+                _ => {
+                    return Err(format!(
+                        "bindings for key {} must be a string or array",
+                        chip8_key
+                    ))
+                }
+            };
+
+            bindings[index as usize] = names
+                .into_iter()
+                .map(|name| {
+                    if HOTKEY_NAMES
+                        .iter()
+                        .any(|hotkey| hotkey.eq_ignore_ascii_case(name))
+                    {
+                        return Err(format!(
+                            "\"{}\" is one of the emulator's controls and can't be bound to key {}",
+                            name, chip8_key
+                        ));
+                    }
+                    Keymap::parse_host_key(name, layout)
+                        .ok_or(format!("unknown key \"{}\" for key {}", name, chip8_key))
+                })
+                .collect::<Result<_, _>>()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn parse_host_key(name: &str, layout: Layout) -> Option<HostKey> {
+        match layout {
+            Layout::Physical => KEY_CODE_NAMES
+                .iter()
+                .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+                .map(|(_, code)| HostKey::Physical(*code)),
+            Layout::Logical => {
+                if let Some((_, named)) = NAMED_KEY_NAMES
+                    .iter()
+                    .find(|(key_name, _)| key_name.eq_ignore_ascii_case(name))
+                {
+                    return Some(HostKey::Logical(Key::Named(*named)));
+                }
+
+                let mut chars = name.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(HostKey::Logical(Key::Character(SmolStr::new(
+                        c.to_lowercase().to_string(),
+                    )))),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    pub fn is_held(&self, chip8_key: usize, input: &WinitInputHelper) -> bool {
+        Keymap::any_held(&self.bindings[chip8_key], input)
+    }
+
+    pub fn is_held_on_second_keypad(&self, chip8_key: usize, input: &WinitInputHelper) -> bool {
+        Keymap::any_held(&self.second_bindings[chip8_key], input)
+    }
+
+    fn any_held(host_keys: &[HostKey], input: &WinitInputHelper) -> bool {
+        host_keys.iter().any(|host_key| match host_key {
+            HostKey::Physical(code) => input.key_held(*code),
+            HostKey::Logical(Key::Character(c)) => {
+                // Holding shift turns "a" into "A", which should still count
+                input.key_held_logical(Key::Character(c))
+                    || input.key_held_logical(Key::Character(&c.to_uppercase()))
+            }
+            HostKey::Logical(key) => input.key_held_logical(key.as_ref()),
+        })
+    }
+
+    // Binds arrow keys (or WASD) and space to a game's directions and fire button. Those host
+    // keys are taken away from any other CHIP-8 key first, so one press never hits two keys,
+    // and a key left with nothing bound swaps in the bindings its host key was given to
+    pub fn bind_directions(&mut self, directions: &Directions, keys: DirectionalKeys) {
+        let host_codes = match keys {
+            DirectionalKeys::Arrows => [
+                KeyCode::ArrowUp,
+                KeyCode::ArrowDown,
+                KeyCode::ArrowLeft,
+                KeyCode::ArrowRight,
+            ],
+            DirectionalKeys::Wasd => [KeyCode::KeyW, KeyCode::KeyS, KeyCode::KeyA, KeyCode::KeyD],
+        };
+        let targets = [
+            (directions.up, host_codes[0]),
+            (directions.down, host_codes[1]),
+            (directions.left, host_codes[2]),
+            (directions.right, host_codes[3]),
+            (directions.fire, KeyCode::Space),
+        ];
+
+        for (chip8_key, code) in targets {
+            let Some(chip8_key) = chip8_key else {
+                continue;
+            };
+            let host_key = HostKey::Physical(code);
+            if self.bindings[chip8_key].contains(&host_key) {
+                continue;
+            }
+            if let Some(displaced) = self
+                .bindings
+                .iter()
+                .position(|bindings| bindings.contains(&host_key))
+            {
+                self.bindings[displaced].retain(|bound| *bound != host_key);
+                if self.bindings[displaced].is_empty() {
+                    self.bindings[displaced] = std::mem::take(&mut self.bindings[chip8_key]);
+                }
+            }
+            self.bindings[chip8_key].push(host_key);
+        }
+    }
+
+    pub fn host_key_name(host_key: &HostKey) -> String {
+        match host_key {
+            HostKey::Physical(code) => {
+                let name = KEY_CODE_NAMES
+                    .iter()
+                    .find(|(_, key_code)| key_code == code)
+                    .map_or("?", |(name, _)| name);
+                name.strip_prefix("Key")
+                    .or_else(|| name.strip_prefix("Digit"))
+                    .unwrap_or(name)
+                    .to_string()
+            }
+            HostKey::Logical(Key::Character(c)) => c.to_uppercase(),
+            HostKey::Logical(key) => NAMED_KEY_NAMES
+                .iter()
+                .find(|(_, named)| *key == *named)
+                .map_or("?", |(name, _)| name)
+                .to_string(),
+        }
+    }
+
+    pub fn describe(&self, chip8_key: usize) -> String {
+        let names: Vec<String> = self.bindings[chip8_key]
+            .iter()
+            .map(Keymap::host_key_name)
+            .collect();
+        if names.is_empty() {
+            "unbound".to_string()
+        } else {
+            names.join("/")
+        }
+    }
+
+    // Lays out the keys a rom uses the same way as the VIP keypad, with their bindings
+    pub fn help_lines(&self, usage: &KeyUsage) -> Vec<(String, [u8; 4])> {
+        let mut lines = vec![
+            ("Controls (F1 to close)".to_string(), HIGHLIGHT_COLOR),
+            (String::new(), TEXT_COLOR),
+        ];
+
+        if !usage.any() {
+            lines.push(("This rom never reads the keypad.".to_string(), TEXT_COLOR));
+            return lines;
+        }
+
+        // A rom that only waits for key presses could be reading any of them
+        let show_all = !usage.keys.contains(&true);
+        for row in KEYPAD_LAYOUT {
+            let mut line = String::new();
+            for key in row {
+                let cell = if show_all || usage.keys[key] {
+                    format!("{:X}: {}", key, self.describe(key))
+                } else {
+                    String::new()
+                };
+                line.push_str(&format!("{:<20}", cell));
+            }
+            lines.push((line.trim_end().to_string(), TEXT_COLOR));
+            lines.push((String::new(), TEXT_COLOR));
+        }
+
+        if usage.waits_for_key {
+            lines.push((
+                "Also waits for key presses, where any key may be used.".to_string(),
+                DIM_COLOR,
+            ));
+        }
+        if usage.unresolved_checks {
+            lines.push((
+                "Some key checks couldn't be traced, so other keys may be used too.".to_string(),
+                DIM_COLOR,
+            ));
+        }
+
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys_table(text: &str) -> TomlTable {
+        Toml::parse(text).unwrap()
+    }
+
+    #[test]
+    fn hotkeys_cannot_be_bound() {
+        let mut keymap = Keymap::default();
+        for text in ["[keys]\n5 = \"Tab\"", "[keys]\n5 = [\"KeyQ\", \"f3\"]"] {
+            let error = keymap.apply_table(&keys_table(text)).unwrap_err();
+            assert!(error.contains("emulator's controls"), "{}", error);
+        }
+
+        let logical = "layout = \"logical\"\n[keys]\n5 = \"Escape\"";
+        assert!(keymap.apply_table(&keys_table(logical)).is_err());
+        assert_eq!(keymap.bindings[5], vec![HostKey::Physical(KeyCode::KeyW)]);
+    }
+
+    #[test]
+    fn wasd_leaves_every_key_bound() {
+        let mut keymap = Keymap::default();
+        let directions = Directions {
+            up: Some(0x2),
+            down: Some(0x8),
+            left: Some(0x4),
+            right: Some(0x6),
+            fire: Some(0x5),
+        };
+        keymap.bind_directions(&directions, DirectionalKeys::Wasd);
+
+        let physical = |codes: &[KeyCode]| -> Vec<HostKey> {
+            codes.iter().map(|code| HostKey::Physical(*code)).collect()
+        };
+        assert_eq!(keymap.bindings[0x2], physical(&[KeyCode::KeyW]));
+        assert_eq!(keymap.bindings[0x8], physical(&[KeyCode::KeyS]));
+        assert_eq!(keymap.bindings[0x4], physical(&[KeyCode::KeyA]));
+        assert_eq!(keymap.bindings[0x6], physical(&[KeyCode::KeyD]));
+        assert_eq!(
+            keymap.bindings[0x5],
+            physical(&[KeyCode::Digit2, KeyCode::Space])
+        );
+        assert_eq!(keymap.bindings[0x7], physical(&[KeyCode::KeyQ]));
+        assert_eq!(keymap.bindings[0x9], physical(&[KeyCode::KeyE]));
+        assert!(keymap.bindings.iter().all(|bindings| !bindings.is_empty()));
+    }
+
+    #[test]
+    fn arrows_are_added_to_the_existing_bindings() {
+        let mut keymap = Keymap::default();
+        let directions = Directions {
+            up: Some(0x1),
+            down: Some(0x4),
+            ..Default::default()
+        };
+        keymap.bind_directions(&directions, DirectionalKeys::Arrows);
+
+        assert_eq!(
+            keymap.bindings[0x1],
+            vec![
+                HostKey::Physical(KeyCode::Digit1),
+                HostKey::Physical(KeyCode::ArrowUp)
+            ]
+        );
+        assert_eq!(keymap.bindings[0x4].len(), 2);
+    }
+}
@@ -0,0 +1,196 @@
+use super::loader::Loader;
+use super::overlay::{Overlay, COLUMNS, DIM_COLOR, HIGHLIGHT_COLOR, ROWS, TEXT_COLOR};
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// How many roms are listed at once, the rest of the overlay shows the selected rom's details
+const LIST_ROWS: usize = 12;
+
+// A rom file, with the details from the "Title [Author, Year]" naming convention
+#[derive(Clone, Debug)]
+pub struct RomEntry {
+    pub path: PathBuf,
+    pub title: String,
+    pub author: Option<String>,
+    pub year: Option<String>,
+    pub description: Option<String>,
+}
+
+impl RomEntry {
+    pub fn from_path(path: &Path) -> Self {
+        let stem = path
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let (title, author, year) = RomEntry::parse_name(&stem);
+
+        Self {
+            path: path.to_path_buf(),
+            title,
+            author,
+            year,
+            description: Loader::load_doc(path),
+        }
+    }
+
+    // Handles "Title [Author, Year]", "Title [Author] (alt)" and "Title (Author, Year)",
+    // anything else is used as the title as it is
+    pub fn parse_name(name: &str) -> (String, Option<String>, Option<String>) {
+        let (title, credits) = if let Some((before, rest)) = name.split_once(" [") {
+            match rest.split_once(']') {
+                // Whatever follows the credits, like "(alt)", belongs with the title
+                Some((credits, after)) => (format!("{}{}", before, after), credits.to_string()),
+                None => return (name.to_string(), None, None),
+            }
+        } else {
+            match name
+                .strip_suffix(')')
+                .and_then(|rest| rest.rsplit_once(" ("))
+            {
+                // Only treated as credits if there's a year, "Pong (1 player)" is part of the title
+                Some((before, credits)) if RomEntry::split_year(credits).1.is_some() => {
+                    (before.to_string(), credits.to_string())
+                }
+                _ => return (name.to_string(), None, None),
+            }
+        };
+
+        let (author, year) = RomEntry::split_year(&credits);
+        (title.trim().to_string(), author, year)
+    }
+
+    // Splits "Author, Year" where either part may be missing
+    fn split_year(credits: &str) -> (Option<String>, Option<String>) {
+        let is_year = |text: &str| text.len() == 4 && text.chars().all(|c| c.is_ascii_digit());
+        let (author, year) = match credits.rsplit_once(", ") {
+            Some((author, year)) if is_year(year) => (author, Some(year)),
+            _ if is_year(credits) => ("", Some(credits)),
+            _ => (credits, None),
+        };
+
+        let author = Some(author.trim().to_string()).filter(|author| !author.is_empty());
+        (author, year.map(|year| year.to_string()))
+    }
+
+    pub fn credits(&self) -> Option<String> {
+        match (&self.author, &self.year) {
+            (Some(author), Some(year)) => Some(format!("{}, {}", author, year)),
+            (Some(author), None) => Some(author.clone()),
+            (None, Some(year)) => Some(year.clone()),
+            (None, None) => None,
+        }
+    }
+}
+
+/// The rom browser shown when no rom is given, or when switching games.
+pub struct Launcher {
+    pub directory: PathBuf,
+    pub entries: Vec<RomEntry>,
+    pub selected: usize,
+}
+
+impl Launcher {
+    pub fn scan(directory: &Path) -> io::Result<Self> {
+        let mut entries = Vec::new();
+        for file in fs::read_dir(directory)? {
+            let path = file?.path();
+            let is_rom = path.extension().is_some_and(|extension| {
+                ["ch8", "c8x", "8o", "gif"]
+                    .iter()
+                    .any(|rom_extension| extension.eq_ignore_ascii_case(rom_extension))
+            });
+            if is_rom && path.is_file() {
+                entries.push(RomEntry::from_path(&path));
+            }
+        }
+        entries.sort_by_key(|entry| entry.title.to_lowercase());
+
+        Ok(Self {
+            directory: directory.to_path_buf(),
+            entries,
+            selected: 0,
+        })
+    }
+
+    // Moves the selection by the given number of entries, stopping at either end
+    pub fn move_selection(&mut self, offset: isize) {
+        let last = self.entries.len().saturating_sub(1) as isize;
+        self.selected = (self.selected as isize + offset).clamp(0, last) as usize;
+    }
+
+    pub fn select_path(&mut self, path: &Path) {
+        if let Some(index) = self.entries.iter().position(|entry| entry.path == path) {
+            self.selected = index;
+        }
+    }
+
+    pub fn selected_entry(&self) -> Option<&RomEntry> {
+        self.entries.get(self.selected)
+    }
+
+    pub fn lines(&self, can_resume: bool) -> Vec<(String, [u8; 4])> {
+        let mut lines = vec![
+            (
+                format!(
+                    "Roms in {} ({})",
+                    self.directory.display(),
+                    self.entries.len()
+                ),
+                HIGHLIGHT_COLOR,
+            ),
+            (String::new(), TEXT_COLOR),
+        ];
+
+        // Keep the selection roughly centered while scrolling
+        let first = self
+            .selected
+            .saturating_sub(LIST_ROWS / 2)
+            .min(self.entries.len().saturating_sub(LIST_ROWS));
+        for (index, entry) in self.entries.iter().enumerate().skip(first).take(LIST_ROWS) {
+            let mut line = entry.title.clone();
+            if let Some(credits) = entry.credits() {
+                line.push_str(&format!(" [{}]", credits));
+            }
+
+            if index == self.selected {
+                lines.push((format!("> {}", line), HIGHLIGHT_COLOR));
+            } else {
+                lines.push((format!("  {}", line), TEXT_COLOR));
+            }
+        }
+        while lines.len() < LIST_ROWS + 3 {
+            lines.push((String::new(), TEXT_COLOR));
+        }
+
+        // The description fills whatever is left above the help line
+        let description_rows = ROWS - lines.len() - 2;
+        match self
+            .selected_entry()
+            .and_then(|entry| entry.description.as_ref())
+        {
+            Some(description) => {
+                let wrapped = Overlay::wrap_text(description.trim(), COLUMNS - 2);
+                for line in wrapped.into_iter().take(description_rows) {
+                    lines.push((line, DIM_COLOR));
+                }
+            }
+            None => lines.push(("No description for this rom.".to_string(), DIM_COLOR)),
+        }
+        while lines.len() < ROWS - 1 {
+            lines.push((String::new(), TEXT_COLOR));
+        }
+
+        let escape = if can_resume {
+            "back to the game"
+        } else {
+            "quit"
+        };
+        lines.push((
+            format!("Up/Down to choose, Enter to play, Esc to {}", escape),
+            TEXT_COLOR,
+        ));
+
+        lines
+    }
+}
@@ -1,16 +1,532 @@
-use super::constants::ROM_START;
+use super::analysis::Analyzer;
+use super::cartridge::Cartridge;
+use super::color_board::ColorBoard;
+use super::constants::{MAX_MEMORY_SIZE, REAL_WIDTH, ROM_START};
+use super::database::{RomDatabase, RomProfile};
+use super::memory_map::{MemoryMap, VipLayout};
+use super::octo::Octo;
+use super::patch::Patch;
+use super::platform::{Platform, Quirks};
 use super::processor::*;
-use std::fs::File;
-use std::io::Read;
+use super::sha1::Sha1;
+use super::zip::Zip;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+// Extensions of the roms picked out of zip archives
+const ROM_EXTENSIONS: [&str; 5] = ["ch8", "c8", "sc8", "xo8", "c8x"];
+// Extensions of text files of hex bytes. Not .txt, which is taken by the roms' descriptions.
+const HEX_EXTENSIONS: [&str; 1] = ["hex"];
+// Extensions of the patches picked up next to a rom
+const PATCH_EXTENSIONS: [&str; 2] = ["ips", "bps"];
+// 1260, the jump that starts every hires rom, and the start of the 1802 interrupt routine for
+// the two page display that it jumps over
+const HIRES_HEADER: [u8; 8] = [0x12, 0x60, 0x01, 0x7A, 0x42, 0x70, 0x22, 0x78];
+
+// What was loaded, for reporting to the user
+#[derive(Clone, Debug)]
+pub struct RomInfo {
+    pub size: usize,
+    pub sha1: String,
+    pub start: usize,
+    pub end: usize,                  // Exclusive
+    pub platform: Platform,          // The platform whose memory size the rom was loaded with
+    pub profile: Option<RomProfile>, // From the rom database, or the cartridge it came in
+    pub cartridge: bool,             // Assembled from an Octo cartridge
+    pub patch: Option<PathBuf>,      // Applied to the rom before it was loaded
+}
+
+#[derive(Clone, Debug)]
+pub enum LoadError {
+    Unreadable {
+        path: String,
+        error: String,
+    },
+    Empty {
+        path: String,
+    },
+    Invalid {
+        path: String,
+        error: String,
+    },
+    Patch {
+        path: String,
+        error: String,
+    },
+    TooLarge {
+        path: String,
+        size: usize,
+        limit: usize,
+        platform: Platform,
+        memory_map: MemoryMap,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LoadError::Unreadable { path, error } => {
+                write!(f, "Failed to read {}: {}", path, error)
+            }
+            LoadError::Empty { path } => write!(f, "{} is empty", path),
+            LoadError::Invalid { path, error } => write!(f, "Failed to load {}: {}", path, error),
+            LoadError::Patch { path, error } => write!(f, "Failed to apply {}: {}", path, error),
+            LoadError::TooLarge {
+                path,
+                size,
+                limit,
+                platform,
+                memory_map,
+            } => {
+                write!(
+                    f,
+                    "{} is {} bytes, too large for {} which has room for {} bytes",
+                    path,
+                    size,
+                    platform.name(),
+                    limit
+                )?;
+                if *memory_map == MemoryMap::Vip {
+                    write!(f, " below the stack with the VIP's memory map")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
 
 pub struct Loader {}
 
 impl Loader {
-    pub fn load_rom(processor: &mut Processor, filename: &str) {
-        let mut buffer = Vec::new();
-        let mut file = File::open(filename).expect("Failed to open rom");
-        file.read_to_end(&mut buffer).expect("Failed to read rom");
+    // Loads the rom into memory and applies its profile from the rom database, if it has one.
+    // The patch is applied first, or without one any patch next to the rom. The hash is of the
+    // patched rom, so patched versions can have their own database entries. A platform given
+    // here is used whatever the rom is recognized as, with its quirks and stack depth. The
+    // processor's memory map has to be set already, as under the VIP's the rom has to end
+    // before the stack.
+    pub fn load_rom(
+        processor: &mut Processor,
+        filename: &str,
+        patch: Option<&Path>,
+        forced_platform: Option<Platform>,
+        database: &RomDatabase,
+    ) -> Result<RomInfo, LoadError> {
+        let (mut rom, cartridge) = Loader::read_rom(filename)?;
+        let patch = patch
+            .map(Path::to_path_buf)
+            .or_else(|| Loader::find_patch(Path::new(filename)));
+        if let Some(patch) = &patch {
+            let path = patch.display().to_string();
+            let data = fs::read(patch).map_err(|e| LoadError::Unreadable {
+                path: path.clone(),
+                error: e.to_string(),
+            })?;
+            rom = Patch::apply(&rom, &data).map_err(|error| LoadError::Patch { path, error })?;
+        }
+        if rom.is_empty() {
+            return Err(LoadError::Empty {
+                path: filename.to_string(),
+            });
+        }
+
+        // The platform decides how much memory there is. Roms that aren't in the database are
+        // guessed at from their instructions, so unknown XO-CHIP roms still get 64K. The database
+        // is trusted over a cartridge's options, which are often just Octo's defaults.
+        let sha1 = Sha1::hex_digest(&rom);
+        let profile = database.lookup(&sha1).or(cartridge.clone());
+        let platform = forced_platform
+            .or_else(|| profile.as_ref().and_then(|profile| profile.platform))
+            .or_else(|| Loader::platform_for_extension(Path::new(filename)))
+            .unwrap_or_else(|| Loader::guess_platform(&rom));
+
+        let start = platform.rom_start();
+        let end = match processor.memory_map {
+            MemoryMap::Separate => platform.memory_size(),
+            MemoryMap::Vip => {
+                VipLayout::for_display(REAL_WIDTH * platform.display_height() / 8).stack_start
+            }
+        };
+        let limit = end - start;
+        if rom.len() > limit {
+            return Err(LoadError::TooLarge {
+                path: filename.to_string(),
+                size: rom.len(),
+                limit,
+                platform,
+                memory_map: processor.memory_map,
+            });
+        }
+        processor.set_memory_size(platform.memory_size());
+        processor.memory[start..start + rom.len()].copy_from_slice(&rom);
+        processor.set_display_height(platform.display_height());
+        processor.PC = platform.program_start();
+        processor.platform = platform;
+        if platform == Platform::Chip8X {
+            processor.color_board = Some(ColorBoard::default());
+        }
+
+        if let Some(profile) = &profile {
+            if let Some(platform) = profile.platform {
+                processor.set_stack_depth(platform.stack_depth());
+            }
+            if let Some(quirks) = profile.quirks {
+                processor.quirks = quirks;
+            }
+            if let Some(speed) = profile.speed {
+                processor.instructions_per_frame = speed;
+            }
+        }
+        if forced_platform.is_some() {
+            processor.set_stack_depth(platform.stack_depth());
+            processor.quirks = Quirks::for_platform(platform);
+        }
+
+        Ok(RomInfo {
+            size: rom.len(),
+            sha1,
+            start,
+            end: start + rom.len(),
+            platform,
+            profile,
+            cartridge: cartridge.is_some(),
+            patch,
+        })
+    }
+
+    // Reads a rom from a file, or standard input for "-". Zip archives are unpacked, Octo source
+    // and cartridges are assembled and text files of hex bytes are decoded. Those have to be
+    // named .hex, unless they're nothing but hex digits, so binary roms that happen to be valid
+    // text aren't mistaken for them. Cartridges also come with a profile made from their
+    // options.
+    fn read_rom(filename: &str) -> Result<(Vec<u8>, Option<RomProfile>), LoadError> {
+        let unreadable = |e: io::Error| LoadError::Unreadable {
+            path: filename.to_string(),
+            error: e.to_string(),
+        };
+        let data = if filename == "-" {
+            let mut data = Vec::new();
+            io::stdin().read_to_end(&mut data).map_err(unreadable)?;
+            data
+        } else {
+            fs::read(filename).map_err(unreadable)?
+        };
+
+        let invalid = |error| LoadError::Invalid {
+            path: filename.to_string(),
+            error,
+        };
+        if Zip::is_zip(&data) {
+            return Ok((
+                Loader::read_zip(&data, filename == "-").map_err(invalid)?,
+                None,
+            ));
+        }
+        if Cartridge::is_cartridge(&data) {
+            let (rom, profile) = Cartridge::load(&data).map_err(invalid)?;
+            return Ok((rom, Some(profile)));
+        }
+        // Octo source is only told apart from other text by its name
+        let is_octo = Path::new(filename)
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("8o"));
+        if is_octo {
+            let rom = Octo::assemble(&String::from_utf8_lossy(&data))
+                .map_err(|e| invalid(format!("the program doesn't assemble, {}", e)))?;
+            return Ok((rom, None));
+        }
+        let hex_named = Path::new(filename).extension().is_some_and(|extension| {
+            HEX_EXTENSIONS
+                .iter()
+                .any(|hex| extension.eq_ignore_ascii_case(hex))
+        });
+        if hex_named {
+            let rom = Loader::parse_hex(&data)
+                .ok_or_else(|| invalid("not a list of hex bytes".to_string()))?;
+            return Ok((rom, None));
+        }
+        if Loader::is_bare_hex(&data) {
+            if let Some(rom) = Loader::parse_hex(&data) {
+                return Ok((rom, None));
+            }
+        }
+        Ok((data, None))
+    }
+
+    fn is_bare_hex(data: &[u8]) -> bool {
+        data.iter()
+            .all(|byte| byte.is_ascii_hexdigit() || byte.is_ascii_whitespace())
+    }
+
+    // Takes the archive's only file or only rom, and asks which one when there are several
+    fn read_zip(data: &[u8], from_stdin: bool) -> Result<Vec<u8>, String> {
+        let entries = Zip::entries(data)?;
+        let roms: Vec<_> = entries
+            .iter()
+            .filter(|entry| {
+                let extension = Path::new(&entry.name).extension();
+                extension.is_some_and(|extension| {
+                    ROM_EXTENSIONS.contains(&extension.to_string_lossy().to_lowercase().as_str())
+                })
+            })
+            .collect();
+        let candidates: Vec<_> = if roms.is_empty() {
+            entries.iter().collect()
+        } else {
+            roms
+        };
+
+        let entry = match candidates.as_slice() {
+            [] => return Err("the archive is empty".to_string()),
+            [entry] => *entry,
+            _ => {
+                let names: Vec<&str> = candidates.iter().map(|entry| entry.name.as_str()).collect();
+                candidates[Loader::choose(&names, from_stdin)?]
+            }
+        };
+        Zip::extract(data, entry, MAX_MEMORY_SIZE)
+    }
+
+    // Asks on the terminal, as there's no window yet when the rom is loaded. There's no asking
+    // when the archive itself came in on standard input.
+    fn choose(names: &[&str], from_stdin: bool) -> Result<usize, String> {
+        if from_stdin || !io::stdin().is_terminal() {
+            return Err(format!(
+                "the archive has several roms ({}), unpack the one to play",
+                names.join(", ")
+            ));
+        }
+
+        println!("The archive has several roms:");
+        for (i, name) in names.iter().enumerate() {
+            println!("  {}) {}", i + 1, name);
+        }
+        loop {
+            print!("Which one? ");
+            io::stdout().flush().ok();
+            let mut answer = String::new();
+            if io::stdin().lock().read_line(&mut answer).unwrap_or(0) == 0 {
+                return Err("no rom was picked".to_string());
+            }
+            match answer.trim().parse::<usize>() {
+                Ok(choice) if (1..=names.len()).contains(&choice) => return Ok(choice - 1),
+                _ => println!("Enter a number from 1 to {}", names.len()),
+            }
+        }
+    }
+
+    // Decodes hex dumps like "00E0 A22A", "0x00, 0xE0" or "200: 00 E0". Addresses ending in ':'
+    // are skipped, as are comments starting with '#', ';' or "//". Returns None for anything
+    // else, like a normal binary rom.
+    fn parse_hex(data: &[u8]) -> Option<Vec<u8>> {
+        let text = std::str::from_utf8(data).ok()?;
+        let mut bytes = Vec::new();
+
+        for line in text.lines() {
+            let line = line.split(['#', ';']).next().unwrap_or_default();
+            let line = line.split("//").next().unwrap_or_default();
+            for token in line
+                .split([' ', '\t', ','])
+                .filter(|token| !token.is_empty())
+            {
+                if token.ends_with(':') {
+                    continue;
+                }
+                let digits = token
+                    .strip_prefix("0x")
+                    .or_else(|| token.strip_prefix("0X"))
+                    .or_else(|| token.strip_prefix('$'))
+                    .unwrap_or(token);
+                if digits.is_empty() || digits.len() % 2 != 0 {
+                    return None;
+                }
+                for i in (0..digits.len()).step_by(2) {
+                    bytes.push(u8::from_str_radix(digits.get(i..i + 2)?, 16).ok()?);
+                }
+            }
+        }
+
+        (!bytes.is_empty()).then_some(bytes)
+    }
+
+    // CHIP-8X roms can't be told apart by their contents, but are usually named .c8x
+    fn platform_for_extension(path: &Path) -> Option<Platform> {
+        path.extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("c8x"))
+            .then_some(Platform::Chip8X)
+    }
+
+    // Hires roms all begin by jumping over their patch to the interpreter, which is checked too
+    // so ordinary roms that happen to start with a jump to 0x260 aren't taken for them
+    fn guess_platform(rom: &[u8]) -> Platform {
+        if rom.starts_with(&HIRES_HEADER) {
+            return Platform::Hires;
+        }
+        let mut memory = vec![0; Platform::XoChip.memory_size()];
+        let end = (ROM_START + rom.len()).min(memory.len());
+        memory[ROM_START..end].copy_from_slice(&rom[..end - ROM_START]);
+        Analyzer::detect_platform(&memory, ROM_START)
+    }
+
+    // A patch named like the rom, "Game.ips" or "Game.bps" next to "Game.ch8"
+    fn find_patch(rom_path: &Path) -> Option<PathBuf> {
+        if rom_path == Path::new("-") {
+            return None;
+        }
+        PATCH_EXTENSIONS
+            .iter()
+            .map(|extension| rom_path.with_extension(extension))
+            .find(|path| path.is_file())
+    }
+
+    // Finds the description that comes with a rom, "Game [Author].txt" next to "Game [Author].ch8".
+    // Alternate versions like "Game [Author] (alt).ch8" share the original's description.
+    pub fn find_doc(rom_path: &Path) -> Option<PathBuf> {
+        let doc_path = rom_path.with_extension("txt");
+        if doc_path.is_file() {
+            return Some(doc_path);
+        }
+
+        let stem = rom_path.file_stem()?.to_str()?;
+        let original = stem.strip_suffix(')')?.rsplit_once(" (")?.0;
+        let doc_path = rom_path.with_file_name(format!("{}.txt", original));
+        doc_path.is_file().then_some(doc_path)
+    }
+
+    pub fn load_doc(rom_path: &Path) -> Option<String> {
+        let text = fs::read(Loader::find_doc(rom_path)?).ok()?;
+        // Some of the older descriptions aren't UTF-8
+        Some(String::from_utf8_lossy(&text).to_string())
+    }
+
+    // The file name of the documentation, or the rom's if there isn't any
+    pub fn doc_title(rom_path: &Path) -> String {
+        let path = Loader::find_doc(rom_path).unwrap_or(rom_path.to_path_buf());
+        path.file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    // Writes a file to the temporary directory, named so tests running at once don't collide
+    fn temp_file(name: &str, data: &[u8]) -> String {
+        let path = std::env::temp_dir().join(format!("chip8-{}-{}", std::process::id(), name));
+        fs::write(&path, data).unwrap();
+        path.to_string_lossy().to_string()
+    }
+
+    fn load(path: &str, patch: Option<&Path>, memory_map: MemoryMap) -> Result<RomInfo, LoadError> {
+        let mut processor = Processor::new(Arc::new(Mutex::new([false; 16])));
+        processor.memory_map = memory_map;
+        Loader::load_rom(&mut processor, path, patch, None, &RomDatabase::load())
+    }
+
+    #[test]
+    fn unreadable() {
+        let result = load("/nonexistent/rom.ch8", None, MemoryMap::Separate);
+        assert!(matches!(result, Err(LoadError::Unreadable { .. })));
+    }
+
+    #[test]
+    fn empty() {
+        let path = temp_file("empty.ch8", &[]);
+        let result = load(&path, None, MemoryMap::Separate);
+        assert!(matches!(result, Err(LoadError::Empty { .. })));
+    }
+
+    #[test]
+    fn invalid() {
+        let path = temp_file("invalid.hex", b"00E0 not hex");
+        let result = load(&path, None, MemoryMap::Separate);
+        assert!(matches!(result, Err(LoadError::Invalid { .. })));
+    }
+
+    #[test]
+    fn bad_patch() {
+        let path = temp_file("patched.ch8", &[0x12, 0x00]);
+        let patch = temp_file("bad.ips", b"not a patch");
+        let result = load(&path, Some(Path::new(&patch)), MemoryMap::Separate);
+        assert!(matches!(result, Err(LoadError::Patch { .. })));
+    }
+
+    #[test]
+    fn too_large() {
+        let path = temp_file("large.ch8", &[0x12; 0xE01]);
+        let result = load(&path, None, MemoryMap::Separate);
+        assert!(matches!(
+            result,
+            Err(LoadError::TooLarge {
+                size: 0xE01,
+                limit: 0xE00,
+                platform: Platform::Chip8,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn too_large_to_end_before_the_vip_stack() {
+        let fits = temp_file("vip-fits.ch8", &[0x12; 3232]);
+        assert_eq!(load(&fits, None, MemoryMap::Vip).unwrap().end, 0xEA0);
+
+        let path = temp_file("vip-large.ch8", &[0x12; 3233]);
+        let result = load(&path, None, MemoryMap::Vip);
+        assert!(matches!(
+            result,
+            Err(LoadError::TooLarge {
+                limit: 3232,
+                memory_map: MemoryMap::Vip,
+                ..
+            })
+        ));
+        assert!(load(&path, None, MemoryMap::Separate).is_ok());
+    }
+
+    #[test]
+    fn hex_dumps() {
+        let program = vec![0x00, 0xE0, 0xA2, 0x2A];
+        assert_eq!(Loader::parse_hex(b"00E0 A22A"), Some(program.clone()));
+        assert_eq!(
+            Loader::parse_hex(b"0x00, 0xE0,\n0xA2, 0x2A // clear"),
+            Some(program.clone())
+        );
+        assert_eq!(
+            Loader::parse_hex(b"200: 00 E0 # clear\n202: $A2 $2A"),
+            Some(program.clone())
+        );
+        assert_eq!(Loader::parse_hex(b"00e0a22a\n"), Some(program));
+
+        assert_eq!(Loader::parse_hex(b"00E"), None);
+        assert_eq!(Loader::parse_hex(b"CLS"), None);
+        assert_eq!(Loader::parse_hex(b"# nothing but a comment"), None);
+        assert_eq!(Loader::parse_hex(&[0x12, 0x00]), None);
+    }
+
+    #[test]
+    fn only_hex_named_text_is_decoded() {
+        let text = b"00E0 A22A # clear";
+        let path = temp_file("notes.txt", text);
+        assert_eq!(
+            load(&path, None, MemoryMap::Separate).unwrap().size,
+            text.len()
+        );
+
+        let path = temp_file("dump.hex", text);
+        assert_eq!(load(&path, None, MemoryMap::Separate).unwrap().size, 4);
+
+        let path = temp_file("bare", b"00E0 A22A\n");
+        assert_eq!(load(&path, None, MemoryMap::Separate).unwrap().size, 4);
+    }
 
-        processor.memory[ROM_START..buffer.len() + ROM_START].copy_from_slice(&buffer)
+    #[test]
+    fn never_asks_which_rom_for_archives_from_standard_input() {
+        let error = Loader::choose(&["a.ch8", "b.ch8"], true).unwrap_err();
+        assert!(error.contains("a.ch8, b.ch8"), "{}", error);
     }
 }
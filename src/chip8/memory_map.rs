@@ -0,0 +1,36 @@
+// The VIP's interpreter kept the call stack, the registers and the display at the top of its
+// 4K. The hires variant's display takes two pages instead of one, and the rest moves down
+// with it.
+const VIP_MEMORY_END: usize = 0x1000;
+const VIP_STACK_BELOW_DISPLAY: usize = 0x60; // 0xEA0 under a one page display
+const VIP_REGISTERS_BELOW_DISPLAY: usize = 0x10; // 0xEF0
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum MemoryMap {
+    Separate, // The display and stack are kept apart, out of the program's reach
+    Vip,      // The display is a bitmap at 0xF00 and the stack is at 0xEA0, as on the VIP
+}
+
+// Where everything is for a display of a given size, in bytes
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct VipLayout {
+    pub stack_start: usize,     // Return addresses, big endian, growing up
+    pub stack_end: usize,       // The 1802's own stack grows down from here
+    pub registers_start: usize, // V0-VF, as machine code sees them
+    pub display_start: usize,
+    pub display_size: usize,
+}
+
+impl VipLayout {
+    pub fn for_display(display_size: usize) -> Self {
+        let display_start = VIP_MEMORY_END - display_size;
+        let stack_start = display_start - VIP_STACK_BELOW_DISPLAY;
+        Self {
+            stack_start,
+            stack_end: stack_start + 0x2F,
+            registers_start: display_start - VIP_REGISTERS_BELOW_DISPLAY,
+            display_start,
+            display_size,
+        }
+    }
+}
@@ -1,6 +1,33 @@
+pub mod analysis;
+pub mod beeper;
+pub mod cartridge;
+pub mod cdp1802;
+pub mod color_board;
+pub mod config;
 pub mod constants;
+pub mod crc32;
+pub mod database;
+pub mod disassembler;
+pub mod doc_viewer;
 pub mod execution;
+pub mod inflate;
+pub mod keymap;
+pub mod launcher;
 pub mod loader;
+pub mod memory_map;
+pub mod octo;
+pub mod overlay;
+pub mod patch;
+pub mod platform;
 pub mod processor;
+pub mod renderer;
+pub mod scheduler;
+pub mod screen;
+pub mod sha1;
+pub mod speed;
 pub mod square;
+pub mod timing;
+pub mod toml;
 pub mod utils;
+pub mod vip;
+pub mod zip;
@@ -0,0 +1,1162 @@
+// Assembles Octo, the language most CHIP-8 homebrew is written and shared in. Follows Octo's
+// own compiler: a jump to "main" at 0x200, labels that can be used before they're defined,
+// macros, string modes and {} expressions evaluated right to left. Programs using SCHIP or
+// XO-CHIP instructions are refused, as the interpreter can't run them.
+use super::constants::ROM_START;
+use std::collections::{HashMap, VecDeque};
+
+const MEMORY_SIZE: usize = 0x10000;
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    quoted: bool, // A "string", which is never an operator or a name
+}
+
+impl Token {
+    fn is(&self, text: &str) -> bool {
+        !self.quoted && self.text == text
+    }
+}
+
+// How a label's address is written into an instruction once it's known
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Fixup {
+    Nnn,        // The low 12 bits of an instruction
+    Long,       // Two bytes, for "i := long" and ":pointer"
+    Unpack,     // The low 12 bits over the two "vx := nn" instructions of ":unpack"
+    UnpackLong, // All 16 bits, for ":unpack long"
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Operand {
+    Register(u8),
+    Byte(u8),
+}
+
+struct Condition {
+    register: u8,
+    operator: String,
+    operand: Option<Operand>,
+}
+
+struct Macro {
+    arguments: Vec<String>,
+    body: Vec<Token>,
+    calls: usize,
+}
+
+// Each alphabet of a string mode expands its own body for the characters in it
+struct StringMode {
+    alphabet: Vec<char>,
+    body: Vec<Token>,
+}
+
+pub struct Octo {
+    tokens: VecDeque<Token>,
+    line: usize,
+    memory: Vec<u8>,
+    here: usize,
+    end: usize, // After the last byte written
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    string_modes: HashMap<String, Vec<StringMode>>,
+    fixups: Vec<(String, usize, Fixup, usize)>, // Label, address, kind, line
+    ifs: Vec<usize>,                            // The jumps of open "if ... begin" blocks
+    loops: Vec<(usize, Vec<usize>)>,            // Loop starts and the jumps out of their "while"s
+    main_jump: bool,
+}
+
+impl Octo {
+    pub fn assemble(source: &str) -> Result<Vec<u8>, String> {
+        let mut octo = Octo {
+            tokens: Octo::tokenize(source)?,
+            line: 1,
+            memory: vec![0; MEMORY_SIZE],
+            here: ROM_START,
+            end: ROM_START,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::from([
+                ("unpack-hi".to_string(), 0x0),
+                ("unpack-lo".to_string(), 0x1),
+                ("compare-temp".to_string(), 0xF),
+            ]),
+            macros: HashMap::new(),
+            string_modes: HashMap::new(),
+            fixups: Vec::new(),
+            ifs: Vec::new(),
+            loops: Vec::new(),
+            main_jump: true,
+        };
+
+        // Room for the jump to main, which is dropped if main comes first anyway
+        octo.instruction(0x00, 0x00)?;
+        while !octo.tokens.is_empty() {
+            octo.statement()?;
+        }
+        octo.finish()
+    }
+
+    fn tokenize(source: &str) -> Result<VecDeque<Token>, String> {
+        let mut tokens = VecDeque::new();
+
+        for (index, line) in source.lines().enumerate() {
+            let mut chars = line.chars().peekable();
+            loop {
+                while chars.next_if(|c| c.is_whitespace()).is_some() {}
+                match chars.peek() {
+                    None | Some('#') => break,
+                    Some('"') => {
+                        chars.next();
+                        let mut text = String::new();
+                        loop {
+                            match chars.next() {
+                                Some('"') => break,
+                                Some('\\') => match chars.next() {
+                                    Some('n') => text.push('\n'),
+                                    Some('t') => text.push('\t'),
+                                    Some('r') => text.push('\r'),
+                                    Some('0') => text.push('\0'),
+                                    Some(c) => text.push(c),
+                                    None => break,
+                                },
+                                Some(c) => text.push(c),
+                                None => {
+                                    return Err(format!("line {}: unterminated string", index + 1))
+                                }
+                            }
+                        }
+                        tokens.push_back(Token {
+                            text,
+                            line: index + 1,
+                            quoted: true,
+                        });
+                    }
+                    Some(_) => {
+                        let mut text = String::new();
+                        while let Some(c) = chars.next_if(|c| !c.is_whitespace()) {
+                            text.push(c);
+                        }
+                        tokens.push_back(Token {
+                            text,
+                            line: index + 1,
+                            quoted: false,
+                        });
+                    }
+                }
+            }
+        }
+
+        Ok(tokens)
+    }
+
+    fn error(&self, message: &str) -> String {
+        format!("line {}: {}", self.line, message)
+    }
+
+    fn unsupported(&self, instruction: &str, platform: &str) -> String {
+        self.error(&format!(
+            "\"{}\" is a {} instruction, which this interpreter can't run",
+            instruction, platform
+        ))
+    }
+
+    fn next(&mut self) -> Result<Token, String> {
+        let token = self
+            .tokens
+            .pop_front()
+            .ok_or_else(|| self.error("unexpected end of the program"))?;
+        self.line = token.line;
+        Ok(token)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.is(text))
+    }
+
+    fn expect(&mut self, text: &str) -> Result<(), String> {
+        if self.next()?.is(text) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected \"{}\"", text)))
+        }
+    }
+
+    fn name(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if token.quoted
+            || Octo::number(&token.text).is_some()
+            || Octo::register_number(&token.text).is_some()
+        {
+            return Err(self.error(&format!("\"{}\" can't be used as a name", token.text)));
+        }
+        Ok(token.text)
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        let token = self.next()?;
+        if !token.quoted {
+            return Err(self.error("expected a string"));
+        }
+        Ok(token.text)
+    }
+
+    // Decimal, 0x hex or 0b binary, optionally negative
+    fn number(text: &str) -> Option<f64> {
+        let (negative, digits) = match text.strip_prefix('-') {
+            Some(digits) => (true, digits),
+            None => (false, text),
+        };
+        let value = if let Some(hex) = digits.strip_prefix("0x") {
+            i64::from_str_radix(hex, 16).ok()? as f64
+        } else if let Some(binary) = digits.strip_prefix("0b") {
+            i64::from_str_radix(binary, 2).ok()? as f64
+        } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+            digits.parse().ok()?
+        } else {
+            return None;
+        };
+        Some(if negative { -value } else { value })
+    }
+
+    fn register_number(text: &str) -> Option<u8> {
+        let digit = text.strip_prefix(['v', 'V'])?;
+        if digit.len() != 1 {
+            return None;
+        }
+        u8::from_str_radix(digit, 16).ok()
+    }
+
+    fn register_named(&self, token: &Token) -> Option<u8> {
+        if token.quoted {
+            return None;
+        }
+        self.aliases
+            .get(&token.text)
+            .copied()
+            .or_else(|| Octo::register_number(&token.text))
+    }
+
+    fn peek_register(&self) -> bool {
+        self.tokens
+            .front()
+            .is_some_and(|token| self.register_named(token).is_some())
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.register_named(&token)
+            .ok_or_else(|| self.error(&format!("expected a register, not \"{}\"", token.text)))
+    }
+
+    // Numbers, constants and labels that are already defined
+    fn lookup(&self, token: &Token) -> Option<f64> {
+        if token.quoted {
+            return None;
+        }
+        Octo::number(&token.text)
+            .or_else(|| self.constants.get(&token.text).copied())
+            .or_else(|| self.labels.get(&token.text).map(|address| *address as f64))
+    }
+
+    // A value that has to be known already, or a {} expression
+    fn value(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        if token.is("{") {
+            return self.calc();
+        }
+        self.lookup(&token)
+            .ok_or_else(|| self.error(&format!("undefined name \"{}\"", token.text)))
+    }
+
+    // Bytes can be given as -128 to 255
+    fn byte_value(&mut self) -> Result<u8, String> {
+        let value = self.value()? as i64;
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(&format!("{} doesn't fit in a byte", value)));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble_value(&mut self) -> Result<u8, String> {
+        let value = self.value()? as i64;
+        if !(0..=15).contains(&value) {
+            return Err(self.error(&format!("{} doesn't fit in 4 bits", value)));
+        }
+        Ok(value as u8)
+    }
+
+    // An address written at "at", which can be a label that's only defined further on
+    fn address(&mut self, at: usize, kind: Fixup) -> Result<(), String> {
+        if let Some(token) = self.tokens.front() {
+            if !token.is("{")
+                && self.lookup(token).is_none()
+                && self.register_named(token).is_none()
+            {
+                let name = self.name()?;
+                self.fixups.push((name, at, kind, self.line));
+                return Ok(());
+            }
+        }
+        let value = self.value()? as i64;
+        self.patch(at, kind, value)
+    }
+
+    fn patch(&mut self, at: usize, kind: Fixup, address: i64) -> Result<(), String> {
+        let limit = match kind {
+            Fixup::Nnn | Fixup::Unpack => 0xFFF,
+            Fixup::Long | Fixup::UnpackLong => 0xFFFF,
+        };
+        if !(0..=limit).contains(&address) {
+            return Err(self.error(&format!("address 0x{:X} is out of range", address)));
+        }
+        match kind {
+            Fixup::Nnn => {
+                self.memory[at] |= (address >> 8) as u8;
+                self.memory[at + 1] = address as u8;
+            }
+            Fixup::Long => {
+                self.memory[at] = (address >> 8) as u8;
+                self.memory[at + 1] = address as u8;
+            }
+            Fixup::Unpack | Fixup::UnpackLong => {
+                self.memory[at + 1] |= (address >> 8) as u8;
+                self.memory[at + 3] = address as u8;
+            }
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, byte: u8) -> Result<(), String> {
+        if self.here >= MEMORY_SIZE {
+            return Err(self.error("the program doesn't fit in memory"));
+        }
+        self.memory[self.here] = byte;
+        self.here += 1;
+        self.end = self.end.max(self.here);
+        Ok(())
+    }
+
+    fn instruction(&mut self, high: u8, low: u8) -> Result<(), String> {
+        self.byte(high)?;
+        self.byte(low)
+    }
+
+    // An instruction like 1NNN with a known address
+    fn jump_to(&mut self, opcode: u8, address: usize) -> Result<(), String> {
+        let at = self.here;
+        self.instruction(opcode, 0)?;
+        self.patch(at, Fixup::Nnn, address as i64)
+    }
+
+    // An instruction like 1NNN with an address or label operand
+    fn address_instruction(&mut self, opcode: u8) -> Result<(), String> {
+        let at = self.here;
+        self.instruction(opcode, 0)?;
+        self.address(at, Fixup::Nnn)
+    }
+
+    fn define_label(&mut self, name: String, address: usize) -> Result<(), String> {
+        if self.labels.contains_key(&name) {
+            return Err(self.error(&format!("the label \"{}\" is already defined", name)));
+        }
+        self.labels.insert(name, address);
+        Ok(())
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if !token.quoted {
+            if self.macros.contains_key(&token.text) {
+                return self.expand_macro(&token.text);
+            }
+            if self.string_modes.contains_key(&token.text) {
+                return self.expand_string_mode(&token.text);
+            }
+        }
+        if let Some(register) = self.register_named(&token) {
+            return self.register_operation(register);
+        }
+
+        match token.text.as_str() {
+            _ if token.quoted => return Err(self.error("unexpected string")),
+            ":" => {
+                let name = self.name()?;
+                // Nothing before main, so it can start at 0x200 without a jump
+                if name == "main" && self.here == ROM_START + 2 && self.end == ROM_START + 2 {
+                    self.here = ROM_START;
+                    self.end = ROM_START;
+                    self.main_jump = false;
+                }
+                self.define_label(name, self.here)?;
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.here + 1)?;
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+            }
+            ":calc" => {
+                let name = self.name()?;
+                self.expect("{")?;
+                let value = self.calc()?;
+                self.constants.insert(name, value);
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let register = if self.peek_is("{") {
+                    self.nibble_value()?
+                } else {
+                    self.register()?
+                };
+                self.aliases.insert(name, register);
+            }
+            ":unpack" => {
+                let (high, kind) = if self.peek_is("long") {
+                    self.next()?;
+                    (0, Fixup::UnpackLong)
+                } else {
+                    (self.nibble_value()?, Fixup::Unpack)
+                };
+                let at = self.here;
+                self.instruction(0x60 | self.aliases["unpack-hi"], high << 4)?;
+                self.instruction(0x60 | self.aliases["unpack-lo"], 0)?;
+                self.address(at, kind)?;
+            }
+            ":org" => {
+                let address = self.value()? as i64;
+                if !(0..MEMORY_SIZE as i64).contains(&address) {
+                    return Err(self.error(&format!("address 0x{:X} is out of range", address)));
+                }
+                self.here = address as usize;
+            }
+            ":byte" => {
+                let byte = self.byte_value()?;
+                self.byte(byte)?;
+            }
+            ":pointer" => {
+                let at = self.here;
+                self.instruction(0, 0)?;
+                self.address(at, Fixup::Long)?;
+            }
+            ":call" => self.address_instruction(0x20)?,
+            ":macro" => self.define_macro()?,
+            ":stringmode" => self.define_string_mode()?,
+            // Debugger directives, which have no effect on the program
+            ":proto" | ":breakpoint" => {
+                self.next()?;
+            }
+            ":monitor" => {
+                for _ in 0..2 {
+                    if !self.tokens.front().is_some_and(|token| token.quoted) {
+                        self.value()?;
+                    } else {
+                        self.next()?;
+                    }
+                }
+            }
+            ":assert" => {
+                let message = if self.tokens.front().is_some_and(|token| token.quoted) {
+                    self.string()?
+                } else {
+                    "assertion failed".to_string()
+                };
+                if self.value()? == 0.0 {
+                    return Err(self.error(&message));
+                }
+            }
+            ";" | "return" => self.instruction(0x00, 0xEE)?,
+            "clear" => self.instruction(0x00, 0xE0)?,
+            // The interpreter only runs what the VIP's could, so programs that need more are
+            // turned away here rather than stopping partway through
+            "hires" | "lores" | "exit" | "scroll-left" | "scroll-right" | "scroll-down"
+            | "saveflags" | "loadflags" => return Err(self.unsupported(&token.text, "SCHIP")),
+            "scroll-up" | "audio" | "plane" | "pitch" => {
+                return Err(self.unsupported(&token.text, "XO-CHIP"))
+            }
+            "bcd" => {
+                let x = self.register()?;
+                self.instruction(0xF0 | x, 0x33)?;
+            }
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek_is("-") {
+                    return Err(self.unsupported(&format!("{} vx - vy", token.text), "XO-CHIP"));
+                }
+                let low = if token.text == "save" { 0x55 } else { 0x65 };
+                self.instruction(0xF0 | x, low)?;
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let height = self.nibble_value()?;
+                if height == 0 {
+                    return Err(self.unsupported("sprite vx vy 0", "SCHIP"));
+                }
+                self.instruction(0xD0 | x, y << 4 | height)?;
+            }
+            "jump" => self.address_instruction(0x10)?,
+            "jump0" => self.address_instruction(0xB0)?,
+            "native" => self.address_instruction(0x00)?,
+            "delay" | "buzzer" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                let low = if token.text == "delay" { 0x15 } else { 0x18 };
+                self.instruction(0xF0 | x, low)?;
+            }
+            "i" => self.index_operation()?,
+            "if" => {
+                let condition = self.condition()?;
+                let token = self.next()?;
+                if token.is("then") {
+                    self.emit_condition(&condition, false)?;
+                } else if token.is("begin") {
+                    self.emit_condition(&condition, true)?;
+                    self.ifs.push(self.here);
+                    self.instruction(0x10, 0)?;
+                } else {
+                    return Err(self.error("expected \"then\" or \"begin\""));
+                }
+            }
+            "else" => {
+                let jump = self
+                    .ifs
+                    .pop()
+                    .ok_or_else(|| self.error("else without if"))?;
+                self.ifs.push(self.here);
+                self.instruction(0x10, 0)?;
+                self.patch(jump, Fixup::Nnn, self.here as i64)?;
+            }
+            "end" => {
+                let jump = self.ifs.pop().ok_or_else(|| self.error("end without if"))?;
+                self.patch(jump, Fixup::Nnn, self.here as i64)?;
+            }
+            "loop" => self.loops.push((self.here, Vec::new())),
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("while outside a loop"));
+                }
+                let condition = self.condition()?;
+                self.emit_condition(&condition, true)?;
+                let jump = self.here;
+                self.instruction(0x10, 0)?;
+                self.loops.last_mut().unwrap().1.push(jump);
+            }
+            "again" => {
+                let (start, breaks) = self
+                    .loops
+                    .pop()
+                    .ok_or_else(|| self.error("again without loop"))?;
+                self.jump_to(0x10, start)?;
+                for jump in breaks {
+                    self.patch(jump, Fixup::Nnn, self.here as i64)?;
+                }
+            }
+            "{" => {
+                let value = self.calc()? as i64;
+                self.byte(value as u8)?;
+            }
+            _ => match self.lookup(&token) {
+                // Bare numbers are data, and bare labels are calls
+                Some(value) if !self.labels.contains_key(&token.text) => {
+                    let value = value as i64;
+                    if !(-128..=255).contains(&value) {
+                        return Err(self.error(&format!("{} doesn't fit in a byte", value)));
+                    }
+                    self.byte(value as u8)?;
+                }
+                _ => {
+                    self.tokens.push_front(token);
+                    self.address_instruction(0x20)?;
+                }
+            },
+        }
+        Ok(())
+    }
+
+    fn register_operation(&mut self, x: u8) -> Result<(), String> {
+        let operator = self.next()?;
+        let logic = |operator: &str| match operator {
+            ":=" => Some(0x0),
+            "|=" => Some(0x1),
+            "&=" => Some(0x2),
+            "^=" => Some(0x3),
+            "+=" => Some(0x4),
+            "-=" => Some(0x5),
+            ">>=" => Some(0x6),
+            "=-" => Some(0x7),
+            "<<=" => Some(0xE),
+            _ => None,
+        };
+
+        if self.peek_register() {
+            let y = self.register()?;
+            let low = logic(&operator.text)
+                .filter(|_| !operator.quoted)
+                .ok_or_else(|| self.error(&format!("unknown operator \"{}\"", operator.text)))?;
+            return self.instruction(0x80 | x, y << 4 | low);
+        }
+
+        match operator.text.as_str() {
+            ":=" if self.peek_is("random") => {
+                self.next()?;
+                let mask = self.byte_value()?;
+                self.instruction(0xC0 | x, mask)
+            }
+            ":=" if self.peek_is("key") => {
+                self.next()?;
+                self.instruction(0xF0 | x, 0x0A)
+            }
+            ":=" if self.peek_is("delay") => {
+                self.next()?;
+                self.instruction(0xF0 | x, 0x07)
+            }
+            ":=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x60 | x, value)
+            }
+            "+=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x70 | x, value)
+            }
+            "-=" => {
+                let value = self.byte_value()?;
+                self.instruction(0x70 | x, value.wrapping_neg())
+            }
+            _ => Err(self.error(&format!(
+                "\"{}\" needs a register on the right",
+                operator.text
+            ))),
+        }
+    }
+
+    fn index_operation(&mut self) -> Result<(), String> {
+        let operator = self.next()?;
+        if operator.is("+=") {
+            let x = self.register()?;
+            return self.instruction(0xF0 | x, 0x1E);
+        }
+        if !operator.is(":=") {
+            return Err(self.error(&format!("unknown operator \"{}\"", operator.text)));
+        }
+
+        if self.peek_is("hex") {
+            self.next()?;
+            let x = self.register()?;
+            self.instruction(0xF0 | x, 0x29)
+        } else if self.peek_is("bighex") {
+            Err(self.unsupported("i := bighex", "SCHIP"))
+        } else if self.peek_is("long") {
+            Err(self.unsupported("i := long", "XO-CHIP"))
+        } else {
+            self.address_instruction(0xA0)
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let register = self.register()?;
+        let operator = self.next()?;
+        let operand = match operator.text.as_str() {
+            _ if operator.quoted => return Err(self.error("expected a comparison")),
+            "key" | "-key" => None,
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => Some(if self.peek_register() {
+                Operand::Register(self.register()?)
+            } else {
+                Operand::Byte(self.byte_value()?)
+            }),
+            other => return Err(self.error(&format!("unknown comparison \"{}\"", other))),
+        };
+        Ok(Condition {
+            register,
+            operator: operator.text,
+            operand,
+        })
+    }
+
+    // Skips the next instruction when the condition is false, or when it's true if negated
+    fn emit_condition(&mut self, condition: &Condition, negated: bool) -> Result<(), String> {
+        let x = condition.register;
+        let operator = match (negated, condition.operator.as_str()) {
+            (false, operator) => operator,
+            (true, "==") => "!=",
+            (true, "!=") => "==",
+            (true, "key") => "-key",
+            (true, "-key") => "key",
+            (true, ">") => "<=",
+            (true, "<=") => ">",
+            (true, "<") => ">=",
+            (true, _) => "<",
+        };
+
+        match (operator, condition.operand) {
+            ("key", _) => self.instruction(0xE0 | x, 0xA1),
+            ("-key", _) => self.instruction(0xE0 | x, 0x9E),
+            ("==", Some(Operand::Register(y))) => self.instruction(0x90 | x, y << 4),
+            ("==", Some(Operand::Byte(value))) => self.instruction(0x40 | x, value),
+            ("!=", Some(Operand::Register(y))) => self.instruction(0x50 | x, y << 4),
+            ("!=", Some(Operand::Byte(value))) => self.instruction(0x30 | x, value),
+            (_, operand) => {
+                // Compared by subtracting in the temporary register and testing the borrow
+                let temp = self.aliases["compare-temp"];
+                match operand {
+                    Some(Operand::Register(y)) => self.instruction(0x80 | temp, y << 4)?,
+                    Some(Operand::Byte(value)) => self.instruction(0x60 | temp, value)?,
+                    None => {}
+                }
+                let (subtract, skip) = match operator {
+                    ">" => (0x5, 0x3F),
+                    "<" => (0x7, 0x3F),
+                    ">=" => (0x7, 0x4F),
+                    _ => (0x5, 0x4F),
+                };
+                self.instruction(0x80 | temp, x << 4 | subtract)?;
+                self.instruction(skip, 0x01)
+            }
+        }
+    }
+
+    // The tokens up to the matching "}", the opening one already read
+    fn block(&mut self) -> Result<Vec<Token>, String> {
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let token = self.next()?;
+            if token.is("{") {
+                depth += 1;
+            } else if token.is("}") {
+                if depth == 0 {
+                    return Ok(body);
+                }
+                depth -= 1;
+            }
+            body.push(token);
+        }
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let mut arguments = Vec::new();
+        while !self.peek_is("{") {
+            arguments.push(self.name()?);
+        }
+        self.next()?;
+        let body = self.block()?;
+        self.macros.insert(
+            name,
+            Macro {
+                arguments,
+                body,
+                calls: 0,
+            },
+        );
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), String> {
+        let count = self.macros[name].arguments.len();
+        let mut values = HashMap::new();
+        for i in 0..count {
+            let value = self.next()?;
+            values.insert(self.macros[name].arguments[i].clone(), value);
+        }
+
+        let line = self.line;
+        let calls = self.macros[name].calls;
+        values.insert(
+            "CALLS".to_string(),
+            Token {
+                text: calls.to_string(),
+                line,
+                quoted: false,
+            },
+        );
+        let definition = self.macros.get_mut(name).unwrap();
+        definition.calls += 1;
+        let body = Octo::substitute(&definition.body, &values);
+        self.insert_tokens(body);
+        Ok(())
+    }
+
+    fn define_string_mode(&mut self) -> Result<(), String> {
+        let name = self.name()?;
+        let alphabet = self.string()?.chars().collect();
+        self.expect("{")?;
+        let body = self.block()?;
+        self.string_modes
+            .entry(name)
+            .or_default()
+            .push(StringMode { alphabet, body });
+        Ok(())
+    }
+
+    // Expands the body for each character, with CHAR its code, INDEX its position in the text
+    // and VALUE its position in the alphabet
+    fn expand_string_mode(&mut self, name: &str) -> Result<(), String> {
+        let text = self.string()?;
+        let line = self.line;
+        let number = |value: usize| Token {
+            text: value.to_string(),
+            line,
+            quoted: false,
+        };
+
+        let mut expansion = Vec::new();
+        for (index, c) in text.chars().enumerate() {
+            let (mode, value) = self.string_modes[name]
+                .iter()
+                .find_map(|mode| {
+                    let value = mode.alphabet.iter().position(|letter| *letter == c)?;
+                    Some((mode, value))
+                })
+                .ok_or_else(|| self.error(&format!("string mode \"{}\" has no \"{}\"", name, c)))?;
+            let values = HashMap::from([
+                ("CHAR".to_string(), number(c as usize)),
+                ("INDEX".to_string(), number(index)),
+                ("VALUE".to_string(), number(value)),
+            ]);
+            expansion.extend(Octo::substitute(&mode.body, &values));
+        }
+        self.insert_tokens(expansion);
+        Ok(())
+    }
+
+    fn substitute(body: &[Token], values: &HashMap<String, Token>) -> Vec<Token> {
+        body.iter()
+            .map(|token| match values.get(&token.text) {
+                Some(value) if !token.quoted => value.clone(),
+                _ => token.clone(),
+            })
+            .collect()
+    }
+
+    fn insert_tokens(&mut self, tokens: Vec<Token>) {
+        for token in tokens.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+    }
+
+    // An expression, the opening "{" already read. There's no precedence, operators apply to
+    // everything to their right.
+    fn calc(&mut self) -> Result<f64, String> {
+        let value = self.calc_expression()?;
+        self.expect("}")?;
+        Ok(value)
+    }
+
+    fn calc_expression(&mut self) -> Result<f64, String> {
+        let left = self.calc_term()?;
+        if self.peek_is("}") || self.peek_is(")") {
+            return Ok(left);
+        }
+
+        let operator = self.next()?;
+        let right = self.calc_expression()?;
+        let (a, b) = (left as i64, right as i64);
+        let truth = |value: bool| if value { 1.0 } else { 0.0 };
+        Ok(match operator.text.as_str() {
+            "+" => left + right,
+            "-" => left - right,
+            "*" => left * right,
+            "/" => left / right,
+            "%" => left % right,
+            "pow" => left.powf(right),
+            "min" => left.min(right),
+            "max" => left.max(right),
+            "&" => (a & b) as f64,
+            "|" => (a | b) as f64,
+            "^" => (a ^ b) as f64,
+            "<<" => a.checked_shl(b as u32).unwrap_or(0) as f64,
+            ">>" => a.checked_shr(b as u32).unwrap_or(0) as f64,
+            "<" => truth(left < right),
+            ">" => truth(left > right),
+            "<=" => truth(left <= right),
+            ">=" => truth(left >= right),
+            "==" => truth(left == right),
+            "!=" => truth(left != right),
+            other => return Err(self.error(&format!("unknown operator \"{}\"", other))),
+        })
+    }
+
+    fn calc_term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        if token.quoted {
+            return Err(self.error("unexpected string in an expression"));
+        }
+
+        let unary = |operator: &str, value: f64| -> Option<f64> {
+            Some(match operator {
+                "-" => -value,
+                "~" => !(value as i64) as f64,
+                "!" => (value == 0.0) as i64 as f64,
+                "sin" => value.sin(),
+                "cos" => value.cos(),
+                "tan" => value.tan(),
+                "exp" => value.exp(),
+                "log" => value.ln(),
+                "abs" => value.abs(),
+                "sqrt" => value.sqrt(),
+                "sign" => value.signum(),
+                "ceil" => value.ceil(),
+                "floor" => value.floor(),
+                _ => return None,
+            })
+        };
+
+        match token.text.as_str() {
+            "(" => {
+                let value = self.calc_expression()?;
+                self.expect(")")?;
+                Ok(value)
+            }
+            "HERE" => Ok(self.here as f64),
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            "@" => {
+                let address = self.calc_term()? as usize;
+                Ok(*self.memory.get(address).unwrap_or(&0) as f64)
+            }
+            operator if unary(operator, 0.0).is_some() => {
+                let value = self.calc_term()?;
+                Ok(unary(operator, value).unwrap())
+            }
+            _ => self
+                .lookup(&token)
+                .ok_or_else(|| self.error(&format!("undefined name \"{}\"", token.text))),
+        }
+    }
+
+    fn finish(mut self) -> Result<Vec<u8>, String> {
+        if !self.ifs.is_empty() {
+            return Err("an \"if ... begin\" is missing its \"end\"".to_string());
+        }
+        if !self.loops.is_empty() {
+            return Err("a \"loop\" is missing its \"again\"".to_string());
+        }
+
+        for (name, at, kind, line) in std::mem::take(&mut self.fixups) {
+            self.line = line;
+            let address = *self
+                .labels
+                .get(&name)
+                .ok_or_else(|| self.error(&format!("undefined name \"{}\"", name)))?;
+            self.patch(at, kind, address as i64)?;
+        }
+
+        if self.main_jump {
+            let main = *self
+                .labels
+                .get("main")
+                .ok_or("the program has no \"main\" label")?;
+            self.memory[ROM_START] = 0x10;
+            self.patch(ROM_START, Fixup::Nnn, main as i64)?;
+        }
+
+        Ok(self.memory[ROM_START..self.end].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn main_first_needs_no_jump() {
+        let source = "
+            : main
+                v0 := 5
+                i := sprite
+                sprite v0 v1 3
+                loop again
+            : sprite
+                0x80 0x40 0x20
+        ";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x60, 0x05, 0xA2, 0x08, 0xD0, 0x13, 0x12, 0x06, 0x80, 0x40, 0x20]
+        );
+    }
+
+    #[test]
+    fn jumps_to_main_after_data() {
+        let source = ": data 1 2 : main jump data";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x12, 0x04, 0x01, 0x02, 0x12, 0x02]
+        );
+    }
+
+    #[test]
+    fn register_and_index_operations() {
+        let source = "
+            : main
+                v1 := v2  v1 |= v2  v1 &= v2  v1 ^= v2  v1 += v2
+                v1 -= v2  v1 >>= v2  v1 =- v2  v1 <<= v2
+                v3 := random 0x0F  v4 := key  v5 := delay  v6 -= 1
+                delay := v7  buzzer := v8  i += v9  i := hex va
+                bcd vb  save vc  load vd  clear  return ;
+                jump0 0x300  native 0x123
+        ";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [
+                0x81, 0x20, 0x81, 0x21, 0x81, 0x22, 0x81, 0x23, 0x81, 0x24, 0x81, 0x25, 0x81, 0x26,
+                0x81, 0x27, 0x81, 0x2E, 0xC3, 0x0F, 0xF4, 0x0A, 0xF5, 0x07, 0x76, 0xFF, 0xF7, 0x15,
+                0xF8, 0x18, 0xF9, 0x1E, 0xFA, 0x29, 0xFB, 0x33, 0xFC, 0x55, 0xFD, 0x65, 0x00, 0xE0,
+                0x00, 0xEE, 0x00, 0xEE, 0xB3, 0x00, 0x01, 0x23
+            ]
+        );
+    }
+
+    #[test]
+    fn labels_before_they_are_defined() {
+        // A bare label is a call
+        let source = ": main sub :call sub jump main : sub ;";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x22, 0x06, 0x22, 0x06, 0x12, 0x00, 0x00, 0xEE]
+        );
+    }
+
+    #[test]
+    fn conditions() {
+        let source = "
+            : main
+                if v2 == 7 then v1 := 1
+                if v2 != v3 then v1 := 1
+                if v0 key then v1 := 1
+                if v0 -key then v1 := 1
+                if v0 < 5 then v1 := 1
+        ";
+        // Comparisons subtract in vF and skip on its borrow flag
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [
+                0x42, 0x07, 0x61, 0x01, 0x52, 0x30, 0x61, 0x01, 0xE0, 0xA1, 0x61, 0x01, 0xE0, 0x9E,
+                0x61, 0x01, 0x6F, 0x05, 0x8F, 0x07, 0x3F, 0x01, 0x61, 0x01
+            ]
+        );
+    }
+
+    #[test]
+    fn if_else_blocks_and_loops() {
+        let source = ": main if v0 == 1 begin v1 := 2 else v1 := 3 end";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x30, 0x01, 0x12, 0x08, 0x61, 0x02, 0x12, 0x0A, 0x61, 0x03]
+        );
+
+        let source = ": main loop v0 += 1 while v0 != 10 again";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x70, 0x01, 0x40, 0x0A, 0x12, 0x08, 0x12, 0x00]
+        );
+    }
+
+    #[test]
+    fn constants_aliases_and_expressions() {
+        let source = "
+            :const SIZE 8
+            :calc DOUBLE { SIZE * 2 }
+            :alias counter v3
+            : main
+                v0 := DOUBLE
+                v1 := { 1 + 2 * 3 }
+                v2 := { 2 * 3 + 1 }
+                counter += 2
+        ";
+        // Right to left, with no precedence
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x60, 0x10, 0x61, 0x07, 0x62, 0x08, 0x73, 0x02]
+        );
+    }
+
+    #[test]
+    fn macros_and_string_modes() {
+        let source = "
+            :macro bump X { X += 1 }
+            :macro count { CALLS }
+            :stringmode letters \"AB\" { VALUE }
+            :stringmode letters \"C\" { CHAR }
+            : main
+                bump v2
+                count count
+                letters \"BAC\"
+        ";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x72, 0x01, 0x00, 0x01, 0x01, 0x00, 0x43]
+        );
+    }
+
+    #[test]
+    fn addresses_in_data() {
+        // :unpack loads an address into v0 and v1, :next labels an instruction's second byte
+        let source = ": main :unpack 0xA data :next count v2 := 5 i := count : data :pointer main";
+        assert_eq!(
+            Octo::assemble(source).unwrap(),
+            [0x60, 0xA2, 0x61, 0x08, 0x62, 0x05, 0xA2, 0x05, 0x02, 0x00]
+        );
+
+        let rom = Octo::assemble(": main jump end :org 0x210 : end ;").unwrap();
+        assert_eq!(rom.len(), 0x12);
+        assert_eq!(rom[..2], [0x12, 0x10]);
+        assert_eq!(rom[0x10..], [0x00, 0xEE]);
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let source = "# a comment\n: main # another\n  clear";
+        assert_eq!(Octo::assemble(source).unwrap(), [0x00, 0xE0]);
+    }
+
+    #[test]
+    fn reports_mistakes_with_their_line() {
+        let error = |source| Octo::assemble(source).unwrap_err();
+        assert_eq!(
+            error(": main\n  jump nowhere"),
+            "line 2: undefined name \"nowhere\""
+        );
+        assert_eq!(
+            error(": main : main"),
+            "line 1: the label \"main\" is already defined"
+        );
+        assert_eq!(
+            error(": main v0 := 256"),
+            "line 1: 256 doesn't fit in a byte"
+        );
+        assert_eq!(error(": main :byte \"x"), "line 1: unterminated string");
+        assert_eq!(error("1 2"), "the program has no \"main\" label");
+        assert_eq!(
+            error(": main if v0 == 1 begin"),
+            "an \"if ... begin\" is missing its \"end\""
+        );
+        assert_eq!(error(": main loop"), "a \"loop\" is missing its \"again\"");
+    }
+
+    #[test]
+    fn refuses_schip_and_xo_chip_instructions() {
+        let error = Octo::assemble(": main\n  plane 1").unwrap_err();
+        assert_eq!(
+            error,
+            "line 2: \"plane\" is a XO-CHIP instruction, which this interpreter can't run"
+        );
+        for source in [
+            ": main hires",
+            ": main scroll-down 4",
+            ": main sprite v0 v1 0",
+            ": main i := bighex v0",
+            ": main i := long main",
+            ": main save v0 - v3",
+        ] {
+            assert!(Octo::assemble(source).is_err(), "{}", source);
+        }
+    }
+}
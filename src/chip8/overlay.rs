@@ -0,0 +1,202 @@
+use super::constants::{TEXT_FONT, TEXT_FONT_FIRST};
+use super::renderer::FitRenderer;
+use pixels::wgpu;
+use pixels::Pixels;
+
+// The overlay has a much higher resolution than the CHIP-8 display so that text stays legible
+pub const OVERLAY_WIDTH: usize = 512;
+pub const OVERLAY_HEIGHT: usize = 256;
+// Each 5x7 glyph gets a 6x9 cell, leaving room between characters and lines
+pub const CELL_WIDTH: usize = 6;
+pub const CELL_HEIGHT: usize = 9;
+pub const COLUMNS: usize = OVERLAY_WIDTH / CELL_WIDTH;
+pub const ROWS: usize = OVERLAY_HEIGHT / CELL_HEIGHT;
+
+pub const TEXT_COLOR: [u8; 4] = [0xff, 0xff, 0xff, 0xff];
+pub const HIGHLIGHT_COLOR: [u8; 4] = [0xff, 0xd0, 0x40, 0xff];
+pub const DIM_COLOR: [u8; 4] = [0x80, 0x80, 0x80, 0xff];
+const BACKGROUND_COLOR: [u8; 4] = [0x00, 0x00, 0x00, 0xd8];
+const BUFFER_CHUNK_SIZE: usize = 4;
+
+/// Text drawn on top of the game, such as help screens and status messages.
+#[derive(Debug)]
+pub struct Overlay {
+    pub visible: bool,
+    pub status: Option<String>, // Set when only a status line is shown, over the game
+    canvas: Vec<u8>,
+    texture: wgpu::Texture,
+    renderer: FitRenderer,
+}
+
+impl Overlay {
+    pub fn new(pixels: &Pixels, surface_size: (u32, u32)) -> Self {
+        let texture = pixels.device().create_texture(&wgpu::TextureDescriptor {
+            label: Some("overlay_texture"),
+            size: wgpu::Extent3d {
+                width: OVERLAY_WIDTH as u32,
+                height: OVERLAY_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        let renderer = FitRenderer::new(
+            pixels.device(),
+            &texture,
+            pixels.render_texture_format(),
+            surface_size,
+            None,
+        );
+
+        Self {
+            visible: false,
+            status: None,
+            canvas: vec![0; OVERLAY_WIDTH * OVERLAY_HEIGHT * BUFFER_CHUNK_SIZE],
+            texture,
+            renderer,
+        }
+    }
+
+    // Replaces the overlay with a dimmed backdrop and the given lines, one per row
+    pub fn show_lines(&mut self, lines: &[(String, [u8; 4])]) {
+        self.clear(BACKGROUND_COLOR);
+        for (row, (line, color)) in lines.iter().enumerate().take(ROWS) {
+            self.draw_text(1, row, line, *color);
+        }
+        self.visible = true;
+        self.status = None;
+    }
+
+    // A single line in the top right corner that leaves the rest of the game uncovered
+    pub fn show_status(&mut self, text: &str) {
+        self.clear([0; 4]);
+        let width = text.chars().count().min(COLUMNS - 1);
+        let column = COLUMNS - width - 1;
+        for y in 0..CELL_HEIGHT + 1 {
+            for x in (column * CELL_WIDTH).saturating_sub(2)..OVERLAY_WIDTH {
+                self.set_pixel(x, y, BACKGROUND_COLOR);
+            }
+        }
+        self.draw_text(column, 0, text, TEXT_COLOR);
+        self.visible = true;
+        self.status = Some(text.to_string());
+    }
+
+    // Splits text into lines no wider than the given number of columns, breaking at spaces
+    // where possible. Tabs are expanded to the next multiple of 8.
+    pub fn wrap_text(text: &str, width: usize) -> Vec<String> {
+        let mut lines = Vec::new();
+
+        for source_line in text.lines() {
+            let mut expanded = String::new();
+            for c in source_line.chars() {
+                if c == '\t' {
+                    expanded.push_str(&" ".repeat(8 - expanded.chars().count() % 8));
+                } else {
+                    expanded.push(c);
+                }
+            }
+
+            let mut remaining: Vec<char> = expanded.trim_end().chars().collect();
+            if remaining.is_empty() {
+                lines.push(String::new());
+            }
+            while !remaining.is_empty() {
+                if remaining.len() <= width {
+                    lines.push(remaining.iter().collect());
+                    break;
+                }
+
+                let split = match remaining[..=width].iter().rposition(|c| *c == ' ') {
+                    Some(space) if space > 0 => space,
+                    _ => width,
+                };
+                lines.push(remaining[..split].iter().collect());
+                remaining = remaining[split..].to_vec();
+                while remaining.first() == Some(&' ') {
+                    remaining.remove(0);
+                }
+            }
+        }
+
+        lines
+    }
+
+    pub fn hide(&mut self) {
+        self.visible = false;
+        self.status = None;
+    }
+
+    pub fn clear(&mut self, color: [u8; 4]) {
+        for pixel in self.canvas.chunks_exact_mut(BUFFER_CHUNK_SIZE) {
+            pixel.copy_from_slice(&color);
+        }
+    }
+
+    // Draws text starting at a character cell, cutting it off at the right edge
+    pub fn draw_text(&mut self, column: usize, row: usize, text: &str, color: [u8; 4]) {
+        for (i, c) in text.chars().enumerate() {
+            if column + i >= COLUMNS {
+                break;
+            }
+            self.draw_char((column + i) * CELL_WIDTH, row * CELL_HEIGHT + 1, c, color);
+        }
+    }
+
+    fn draw_char(&mut self, x: usize, y: usize, c: char, color: [u8; 4]) {
+        // Anything outside printable ASCII is drawn as '?'
+        let index = (c as usize)
+            .checked_sub(TEXT_FONT_FIRST as usize)
+            .filter(|index| *index < TEXT_FONT.len())
+            .unwrap_or('?' as usize - TEXT_FONT_FIRST as usize);
+
+        for (i, row) in TEXT_FONT[index].iter().enumerate() {
+            for j in 0..5 {
+                if row & (0b10000 >> j) != 0 {
+                    self.set_pixel(x + j, y + i, color);
+                }
+            }
+        }
+    }
+
+    fn set_pixel(&mut self, x: usize, y: usize, color: [u8; 4]) {
+        if x >= OVERLAY_WIDTH || y >= OVERLAY_HEIGHT {
+            return;
+        }
+
+        let offset = (y * OVERLAY_WIDTH + x) * BUFFER_CHUNK_SIZE;
+        self.canvas[offset..offset + BUFFER_CHUNK_SIZE].copy_from_slice(&color);
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        self.renderer.resize(queue, width, height);
+    }
+
+    pub fn render(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        render_target: &wgpu::TextureView,
+        queue: &wgpu::Queue,
+    ) {
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &self.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.canvas,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some((OVERLAY_WIDTH * BUFFER_CHUNK_SIZE) as u32),
+                rows_per_image: Some(OVERLAY_HEIGHT as u32),
+            },
+            self.texture.size(),
+        );
+        self.renderer.render(encoder, render_target);
+    }
+}
@@ -0,0 +1,273 @@
+use super::constants::MAX_MEMORY_SIZE;
+use super::crc32::Crc32;
+
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_END: &[u8] = b"EOF";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// The source, target and patch checksums at the end of a BPS patch
+const BPS_FOOTER_SIZE: usize = 12;
+
+// Applies IPS and BPS patches, the formats rom hacks and translations are shared in. Patched
+// roms can't grow past the largest memory, so a bad patch can't ask for gigabytes.
+pub struct Patch {}
+
+impl Patch {
+    pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+        if patch.starts_with(IPS_MAGIC) {
+            Patch::apply_ips(rom, patch)
+        } else if patch.starts_with(BPS_MAGIC) {
+            Patch::apply_bps(rom, patch)
+        } else {
+            Err("not an IPS or BPS patch".to_string())
+        }
+    }
+
+    // Records of a 3 byte offset and 2 byte size followed by the data, or by a 2 byte count
+    // and a byte to repeat when the size is 0. Can end with a size to truncate the rom to.
+    fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+        let mut output = rom.to_vec();
+        let mut position = IPS_MAGIC.len();
+        let mut read = |length: usize| {
+            let bytes = patch
+                .get(position..position + length)
+                .ok_or("the patch is cut off");
+            position += length;
+            bytes
+        };
+        let number = |bytes: &[u8]| bytes.iter().fold(0, |n, byte| n << 8 | *byte as usize);
+
+        loop {
+            let offset = read(3)?;
+            if offset == IPS_END {
+                if let Ok(size) = read(3) {
+                    output.truncate(number(size));
+                }
+                return Ok(output);
+            }
+            let offset = number(offset);
+
+            let (data, length) = match number(read(2)?) {
+                0 => {
+                    let length = number(read(2)?);
+                    (vec![read(1)?[0]; length], length)
+                }
+                length => (read(length)?.to_vec(), length),
+            };
+            if offset + length > MAX_MEMORY_SIZE {
+                return Err(Patch::too_large(offset + length));
+            }
+            if output.len() < offset + length {
+                output.resize(offset + length, 0);
+            }
+            output[offset..offset + length].copy_from_slice(&data);
+        }
+    }
+
+    // A list of actions building the new rom from the old one and the patch, with sizes and
+    // offsets as variable length numbers. The checksums make sure it's the rom the patch is for.
+    fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, String> {
+        if patch.len() < BPS_MAGIC.len() + BPS_FOOTER_SIZE {
+            return Err("the patch is cut off".to_string());
+        }
+        let footer = patch.len() - BPS_FOOTER_SIZE;
+        let checksum = |at: usize| u32::from_le_bytes(patch[at..at + 4].try_into().unwrap());
+        if Crc32::checksum(&patch[..footer + 8]) != checksum(footer + 8) {
+            return Err("the patch is corrupt".to_string());
+        }
+        if Crc32::checksum(rom) != checksum(footer) {
+            return Err("the patch is for a different rom".to_string());
+        }
+
+        let mut reader = BpsReader {
+            data: &patch[..footer],
+            position: BPS_MAGIC.len(),
+        };
+        let source_size = reader.number()?;
+        let target_size = reader.number()?;
+        let metadata_size = reader.number()?;
+        reader.position += metadata_size;
+        if source_size != rom.len() {
+            return Err("the patch is for a different rom".to_string());
+        }
+        if target_size > MAX_MEMORY_SIZE {
+            return Err(Patch::too_large(target_size));
+        }
+
+        let mut output: Vec<u8> = Vec::with_capacity(target_size);
+        let mut source_offset = 0;
+        let mut target_offset = 0;
+        while reader.position < footer {
+            let action = reader.number()?;
+            let length = (action >> 2) + 1;
+            if output.len() + length > target_size {
+                return Err("the patch is corrupt".to_string());
+            }
+            match action & 3 {
+                // Copies from the same place in the old rom
+                0 => {
+                    let start = output.len();
+                    output.extend_from_slice(BpsReader::slice(rom, start, length)?);
+                }
+                // Copies from the patch
+                1 => {
+                    let start = reader.position;
+                    output.extend_from_slice(BpsReader::slice(reader.data, start, length)?);
+                    reader.position += length;
+                }
+                // Copies from elsewhere in the old rom
+                2 => {
+                    source_offset = reader.relative(source_offset)?;
+                    output.extend_from_slice(BpsReader::slice(rom, source_offset, length)?);
+                    source_offset += length;
+                }
+                // Copies from earlier in the new rom, a byte at a time as it can overlap
+                _ => {
+                    target_offset = reader.relative(target_offset)?;
+                    for _ in 0..length {
+                        let byte = *output.get(target_offset).ok_or("the patch is corrupt")?;
+                        output.push(byte);
+                        target_offset += 1;
+                    }
+                }
+            }
+        }
+
+        if output.len() != target_size || Crc32::checksum(&output) != checksum(footer + 4) {
+            return Err("the patched rom doesn't match the patch's checksum".to_string());
+        }
+        Ok(output)
+    }
+
+    fn too_large(size: usize) -> String {
+        format!(
+            "the patched rom would be {} bytes, more than the {} that fit in memory",
+            size, MAX_MEMORY_SIZE
+        )
+    }
+}
+
+struct BpsReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BpsReader<'_> {
+    // Seven bits per byte, low bits first, with the top bit marking the last byte. Each
+    // continuation adds one, so every number has only one encoding.
+    fn number(&mut self) -> Result<usize, String> {
+        let mut number = 0_usize;
+        let mut shift = 1_usize;
+        loop {
+            let byte = *self.data.get(self.position).ok_or("the patch is cut off")?;
+            self.position += 1;
+            number = (byte as usize & 0x7F)
+                .checked_mul(shift)
+                .and_then(|value| number.checked_add(value))
+                .ok_or("the patch is corrupt")?;
+            if byte & 0x80 != 0 {
+                return Ok(number);
+            }
+            shift = shift.checked_shl(7).ok_or("the patch is corrupt")?;
+            number += shift;
+        }
+    }
+
+    // Moves an offset by a signed amount, stored with the sign in the lowest bit
+    fn relative(&mut self, offset: usize) -> Result<usize, String> {
+        let number = self.number()?;
+        let moved = if number & 1 != 0 {
+            offset.checked_sub(number >> 1)
+        } else {
+            offset.checked_add(number >> 1)
+        };
+        moved.ok_or("the patch is corrupt".to_string())
+    }
+
+    fn slice(data: &[u8], start: usize, length: usize) -> Result<&[u8], String> {
+        data.get(start..start + length)
+            .ok_or("the patch is corrupt".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(mut number: usize, output: &mut Vec<u8>) {
+        loop {
+            let low = (number & 0x7F) as u8;
+            number >>= 7;
+            if number == 0 {
+                output.push(0x80 | low);
+                return;
+            }
+            output.push(low);
+            number -= 1;
+        }
+    }
+
+    // Wraps the actions in a BPS header and footer
+    fn bps(source: &[u8], target: &[u8], target_size: usize, actions: &[u8]) -> Vec<u8> {
+        let mut patch = BPS_MAGIC.to_vec();
+        encode(source.len(), &mut patch);
+        encode(target_size, &mut patch);
+        encode(0, &mut patch);
+        patch.extend_from_slice(actions);
+        patch.extend_from_slice(&Crc32::checksum(source).to_le_bytes());
+        patch.extend_from_slice(&Crc32::checksum(target).to_le_bytes());
+        let checksum = Crc32::checksum(&patch);
+        patch.extend_from_slice(&checksum.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn crc32() {
+        assert_eq!(Crc32::checksum(b"123456789"), 0xCBF43926);
+    }
+
+    #[test]
+    fn ips_records() {
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]);
+        // Three 0xCC from offset 5, past the end of the rom
+        patch.extend_from_slice(&[0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x03, 0xCC]);
+        patch.extend_from_slice(IPS_END);
+        assert_eq!(
+            Patch::apply(&[1, 2, 3, 4], &patch).unwrap(),
+            [1, 0xAA, 0xBB, 4, 0, 0xCC, 0xCC, 0xCC]
+        );
+
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(Patch::apply(&[1, 2, 3, 4], &patch).unwrap(), [1, 0xAA]);
+    }
+
+    #[test]
+    fn bps_actions() {
+        // Two bytes from the rom, one from the patch, then the rest of the rom
+        let actions = [0x84, 0x81, b'X', 0x80];
+        let patch = bps(b"ABCD", b"ABXD", 4, &actions);
+        assert_eq!(Patch::apply(b"ABCD", &patch).unwrap(), b"ABXD");
+    }
+
+    #[test]
+    fn bps_checksums() {
+        let actions = [0x84, 0x81, b'X', 0x80];
+        let patch = bps(b"ABCD", b"ABXD", 4, &actions);
+        assert!(Patch::apply(b"ABCE", &patch).is_err());
+
+        let mut corrupt = patch.clone();
+        corrupt[8] ^= 1;
+        assert!(Patch::apply(b"ABCD", &corrupt).is_err());
+
+        let wrong_target = bps(b"ABCD", b"ABYD", 4, &actions);
+        assert!(Patch::apply(b"ABCD", &wrong_target).is_err());
+    }
+
+    #[test]
+    fn bps_too_large() {
+        let patch = bps(b"ABCD", b"", MAX_MEMORY_SIZE + 1, &[0x80]);
+        assert!(Patch::apply(b"ABCD", &patch)
+            .unwrap_err()
+            .contains("fit in memory"));
+    }
+}
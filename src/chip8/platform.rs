@@ -0,0 +1,190 @@
+use super::constants::{
+    CHIP8X_ROM_START, HIRES_HEIGHT, HIRES_PROGRAM_START, REAL_HEIGHT, ROM_START,
+};
+use super::toml::TomlTable;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Platform {
+    Chip8,     // Original COSMAC VIP interpreter
+    SuperChip, // SCHIP on the HP48, which most 90s games were written for
+    XoChip,    // Octo's XO-CHIP
+    Hires,     // The VIP's two-page display variant, 64x64 with programs at 0x260
+    Chip8X,    // The VIP with the VP-590 colour board and a second keypad
+}
+
+impl Platform {
+    pub fn parse(name: &str) -> Option<Platform> {
+        match name.to_lowercase().as_str() {
+            "chip8" | "chip-8" | "vip" => Some(Platform::Chip8),
+            "schip" | "superchip" | "chip48" => Some(Platform::SuperChip),
+            "xochip" | "xo-chip" => Some(Platform::XoChip),
+            "hires" | "chip8-hires" | "hires-chip8" => Some(Platform::Hires),
+            "chip8x" | "chip-8x" => Some(Platform::Chip8X),
+            _ => None,
+        }
+    }
+
+    // How many calls can be nested before the stack overflows
+    pub fn stack_depth(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::Hires | Platform::Chip8X => 12,
+            Platform::SuperChip | Platform::XoChip => 16,
+        }
+    }
+
+    // XO-CHIP extends the address space from 4K to 64K
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Platform::Chip8 | Platform::SuperChip | Platform::Hires | Platform::Chip8X => 0x1000,
+            Platform::XoChip => 0x10000,
+        }
+    }
+
+    pub fn display_height(&self) -> usize {
+        match self {
+            Platform::Hires => HIRES_HEIGHT,
+            _ => REAL_HEIGHT,
+        }
+    }
+
+    // Where the rom is loaded
+    pub fn rom_start(&self) -> usize {
+        match self {
+            Platform::Chip8X => CHIP8X_ROM_START,
+            _ => ROM_START,
+        }
+    }
+
+    // Where execution starts. Hires roms are still loaded at 0x200, but what's there is the
+    // jump over the interpreter patch that follows it.
+    pub fn program_start(&self) -> usize {
+        match self {
+            Platform::Hires => HIRES_PROGRAM_START,
+            _ => self.rom_start(),
+        }
+    }
+
+    // The platforms of the COSMAC VIP, whose interpreters ran 0NNN as 1802 machine code
+    pub fn runs_machine_code(&self) -> bool {
+        match self {
+            Platform::Chip8 | Platform::Hires | Platform::Chip8X => true,
+            Platform::SuperChip | Platform::XoChip => false,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::Chip8 => "chip8",
+            Platform::SuperChip => "schip",
+            Platform::XoChip => "xochip",
+            Platform::Hires => "hires",
+            Platform::Chip8X => "chip8x",
+        }
+    }
+}
+
+// Behaviours that differ between CHIP-8 interpreters. The defaults match how this
+// interpreter has always behaved, which is a mix of the VIP and CHIP-48.
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct Quirks {
+    pub vf_reset: bool,         // 8XY1, 8XY2 and 8XY3 reset VF to 0
+    pub memory_increment: bool, // FX55 and FX65 leave I pointing after the last register
+    pub shift_vx: bool,         // 8XY6 and 8XYE shift VX in place instead of copying VY
+    pub jump_vx: bool,          // BXNN jumps to XNN + VX instead of NNN + V0
+    pub wrap_sprites: bool,     // Sprites wrap around the screen edges instead of clipping
+    pub key_press: bool,        // FX0A returns as soon as a key is down, not once it's released
+}
+
+impl Quirks {
+    pub fn for_platform(platform: Platform) -> Self {
+        match platform {
+            Platform::Chip8 | Platform::Hires | Platform::Chip8X => Self {
+                vf_reset: true,
+                memory_increment: true,
+                ..Default::default()
+            },
+            Platform::SuperChip => Self {
+                shift_vx: true,
+                jump_vx: true,
+                ..Default::default()
+            },
+            Platform::XoChip => Self {
+                memory_increment: true,
+                wrap_sprites: true,
+                ..Default::default()
+            },
+        }
+    }
+
+    // Parses a comma separated list like "schip,wrap_sprites,!jump_vx", where a platform name
+    // switches to its preset and a quirk name turns it on, or off with a leading "!"
+    pub fn parse_list(text: &str) -> Result<Self, String> {
+        let mut quirks = Quirks::default();
+
+        for item in text
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+        {
+            if let Some(platform) = Platform::parse(item) {
+                quirks = Quirks::for_platform(platform);
+                continue;
+            }
+
+            let (name, value) = match item.strip_prefix('!') {
+                Some(name) => (name, false),
+                None => (item, true),
+            };
+            quirks
+                .set(name, value)
+                .map_err(|_| format!("unknown platform or quirk \"{}\"", name))?;
+        }
+
+        Ok(quirks)
+    }
+
+    // Every quirk by name, whether it's on or not
+    pub fn values(&self) -> [(&'static str, bool); 6] {
+        [
+            ("vf_reset", self.vf_reset),
+            ("memory_increment", self.memory_increment),
+            ("shift_vx", self.shift_vx),
+            ("jump_vx", self.jump_vx),
+            ("wrap_sprites", self.wrap_sprites),
+            ("key_press", self.key_press),
+        ]
+    }
+
+    // The names of the quirks that are turned on, for showing to the user
+    pub fn enabled(&self) -> Vec<&'static str> {
+        self.values()
+            .into_iter()
+            .filter(|(_, enabled)| *enabled)
+            .map(|(name, _)| name)
+            .collect()
+    }
+
+    // Applies a table such as "shift_vx = true", leaving quirks that aren't mentioned alone
+    pub fn apply_table(&mut self, table: &TomlTable) -> Result<(), String> {
+        for (name, value) in table {
+            let value = value
+                .as_bool()
+                .ok_or(format!("quirk \"{}\" must be true or false", name))?;
+            self.set(name, value)?;
+        }
+        Ok(())
+    }
+
+    pub fn set(&mut self, name: &str, value: bool) -> Result<(), String> {
+        match name {
+            "vf_reset" => self.vf_reset = value,
+            "memory_increment" => self.memory_increment = value,
+            "shift_vx" => self.shift_vx = value,
+            "jump_vx" => self.jump_vx = value,
+            "wrap_sprites" => self.wrap_sprites = value,
+            "key_press" => self.key_press = value,
+            _ => return Err(format!("unknown quirk \"{}\"", name)),
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,71 @@
+use super::color_board::ColorBoard;
 use super::constants::*;
 use super::execution::*;
-use pixels::Pixels;
+use super::memory_map::{MemoryMap, VipLayout};
+use super::platform::{Platform, Quirks};
+use super::screen::Screen;
+use super::timing::{Timing, VIP_CYCLES_PER_FRAME};
+use super::vip::{Vip, VIP_MEMORY_SIZE};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fmt;
 use std::sync::{Arc, Mutex};
-use std::time::Instant;
+
+// Why the processor has stopped running instructions
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Halt {
+    WaitingForPress { register: usize }, // FX0A, before any key is down
+    WaitingForRelease { register: usize, key: usize }, // FX0A, once a key has gone down
+    Error(ProcessorError),               // The rom did something impossible
+}
+
+// Mistakes in a rom that stop it for good, with the address of the instruction at fault
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ProcessorError {
+    StackOverflow { pc: usize, depth: usize },
+    StackUnderflow { pc: usize },
+    MachineCodeTimeout { pc: usize, address: usize },
+    UnknownInstruction { pc: usize, opcode: u16 },
+    InvalidKey { pc: usize, key: u8 },
+    EndOfMemory { pc: usize },
+}
+
+impl fmt::Display for ProcessorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ProcessorError::StackOverflow { pc, depth } => write!(
+                f,
+                "Stack overflow at {:03X}, calls were nested more than {} deep",
+                pc, depth
+            ),
+            ProcessorError::StackUnderflow { pc } => write!(
+                f,
+                "Stack underflow at {:03X}, returned without a call to return from",
+                pc
+            ),
+            ProcessorError::MachineCodeTimeout { pc, address } => write!(
+                f,
+                "Machine code at {:03X}, called from {:03X}, never returned to the interpreter",
+                address, pc
+            ),
+            ProcessorError::UnknownInstruction { pc, opcode } => write!(
+                f,
+                "Unknown instruction {:04X} at {:03X}, the rom may be for another platform",
+                opcode, pc
+            ),
+            ProcessorError::InvalidKey { pc, key } => write!(
+                f,
+                "Invalid key {:02X} at {:03X}, the keypad only has keys 0-F",
+                key, pc
+            ),
+            ProcessorError::EndOfMemory { pc } => write!(
+                f,
+                "Ran off the end of memory at {:03X}, there's no instruction left to run",
+                pc
+            ),
+        }
+    }
+}
 
 #[allow(non_snake_case)]
 #[derive(Debug)]
@@ -12,11 +75,24 @@ pub struct Processor {
     pub V_REGS: [u8; 16], // Last register is VF (Flag register)
     pub delay_timer: u8,
     pub sound_timer: u8,
-    pub memory: [u8; 4096],
-    pub stack: Vec<[char; 3]>,
-    pub pixels: Option<Pixels>,
-    pub last_execution: Instant,
+    pub memory: Vec<u8>,          // 4K, or 64K for XO-CHIP
+    pub stack: Vec<u16>,          // Return addresses, with a fixed depth set by set_stack_depth
+    pub SP: usize,                // Stack pointer, the number of calls on the stack
+    pub display: Vec<PixelState>, // REAL_WIDTH pixels wide and display_height() tall, row by row
+    pub memory_map: MemoryMap,    // Under the VIP's, the stack and display live in memory
+    pub platform: Platform,       // What the rom was loaded for, which decides what 0NNN does
+    pub quirks: Quirks,
+    pub instructions_per_frame: u32, // Instructions run per 60Hz frame
+    pub timing: Timing,
+    pub halt: Option<Halt>,
+    pub frame_cycles: u32, // Time used so far in the current frame, in instructions or VIP cycles
+    pub rng: StdRng,       // Used by CXNN, can be seeded for repeatable runs
+    pub vip: Option<Vip>,  // When set, an emulated VIP runs its own interpreter instead
+    pub color_board: Option<ColorBoard>, // CHIP-8X's colours, which also enable its instructions
+    pub tone_frequency: Option<f32>, // Set by CHIP-8X's FXF8, otherwise the configured beep plays
+    pub screen: Option<Arc<Mutex<Screen>>>,
     pub pressed_keys: Arc<Mutex<[bool; 16]>>,
+    pub second_keys: Arc<Mutex<[bool; 16]>>, // CHIP-8X's second keypad
 }
 
 impl Processor {
@@ -27,11 +103,24 @@ impl Processor {
             V_REGS: [0; 16],
             delay_timer: 0,
             sound_timer: 0,
-            memory: [0; 4096],
-            stack: Vec::new(),
-            pixels: None,
-            last_execution: Instant::now(),
+            memory: vec![0; DEFAULT_MEMORY_SIZE],
+            stack: vec![0; DEFAULT_STACK_DEPTH],
+            SP: 0,
+            display: vec![PixelState::Off; REAL_WIDTH * REAL_HEIGHT],
+            memory_map: MemoryMap::Separate,
+            platform: Platform::Chip8,
+            quirks: Quirks::default(),
+            instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+            timing: Timing::Uniform,
+            halt: None,
+            frame_cycles: 0,
+            rng: StdRng::from_entropy(),
+            vip: None,
+            color_board: None,
+            tone_frequency: None,
+            screen: None,
             pressed_keys,
+            second_keys: Arc::new(Mutex::new([false; 16])),
         };
 
         // Load font into memory
@@ -42,7 +131,7 @@ impl Processor {
         processor
     }
 
-    pub fn execute(&mut self) {
+    fn execute(&mut self) {
         let byte1 = self.memory[self.PC];
         let byte2 = self.memory[self.PC + 1];
         let nibbles = [byte1 >> 4, byte1 & 0b1111, byte2 >> 4, byte2 & 0b1111]
@@ -50,9 +139,411 @@ impl Processor {
 
         InstructionHandler::execute(self, nibbles);
 
-        // Check that it wasn't a jump or subroutine return
-        if nibbles[0] != '1' && nibbles[0] != '2' && nibbles[0] != 'B' {
+        // Check that it wasn't a jump or subroutine return. BXYN isn't a jump on CHIP-8X.
+        let jumped = match nibbles[0] {
+            '1' | '2' => true,
+            'B' => self.color_board.is_none(),
+            _ => false,
+        };
+        if !jumped {
             self.PC += 2;
         }
     }
+
+    // Keeps whatever was already loaded that still fits
+    pub fn set_memory_size(&mut self, size: usize) {
+        self.memory.resize(size, 0);
+    }
+
+    pub fn set_stack_depth(&mut self, depth: usize) {
+        self.stack = vec![0; depth];
+        self.SP = 0;
+    }
+
+    pub fn display_height(&self) -> usize {
+        self.display.len() / REAL_WIDTH
+    }
+
+    // Clears the display, which is 64 pixels wide either way
+    pub fn set_display_height(&mut self, height: usize) {
+        self.display = vec![PixelState::Off; REAL_WIDTH * height];
+    }
+
+    // Where the stack, registers and display are kept under the VIP's memory map
+    pub fn vip_layout(&self) -> VipLayout {
+        VipLayout::for_display(self.display.len() / 8)
+    }
+
+    // The return addresses of the calls in progress, oldest first
+    pub fn call_stack(&self) -> Vec<u16> {
+        (0..self.SP).map(|index| self.stack_entry(index)).collect()
+    }
+
+    // Under the VIP's memory map return addresses are stored big endian from 0xEA0, where
+    // the program can see and change them. Entries past the end of memory are lost.
+    pub fn stack_entry(&self, index: usize) -> u16 {
+        match self.memory_map {
+            MemoryMap::Separate => self.stack[index],
+            MemoryMap::Vip => {
+                let at = self.vip_layout().stack_start + index * 2;
+                match self.memory.get(at..at + 2) {
+                    Some(bytes) => u16::from_be_bytes([bytes[0], bytes[1]]),
+                    None => 0,
+                }
+            }
+        }
+    }
+
+    pub fn set_stack_entry(&mut self, index: usize, address: u16) {
+        match self.memory_map {
+            MemoryMap::Separate => self.stack[index] = address,
+            MemoryMap::Vip => {
+                let at = self.vip_layout().stack_start + index * 2;
+                if let Some(bytes) = self.memory.get_mut(at..at + 2) {
+                    bytes.copy_from_slice(&address.to_be_bytes());
+                }
+            }
+        }
+    }
+
+    // Hands the loaded rom to a CHIP-8 interpreter running on an emulated VIP, which is limited
+    // to the VIP's 4K
+    pub fn start_vip(&mut self, interpreter: &[u8]) {
+        self.set_memory_size(VIP_MEMORY_SIZE);
+        self.vip = Some(Vip::new(&mut self.memory, interpreter));
+    }
+
+    // Runs the rest of the current frame, then counts the timers down like the VIP's 60Hz
+    // interrupt did. Timing depends only on the instructions run, never on the host.
+    pub fn run_frame(&mut self) {
+        if let Some(vip) = &mut self.vip {
+            let pressed_keys = *self.pressed_keys.lock().unwrap();
+            vip.run_frame(&mut self.memory, &mut self.display, pressed_keys);
+            self.sound_timer = vip.tone() as u8;
+            if let Some(screen) = &self.screen {
+                screen
+                    .lock()
+                    .unwrap()
+                    .draw(&self.display, None)
+                    .expect("Failed to render pixel buffer at the end of a frame");
+            }
+            return;
+        }
+
+        while self.frame_cycles < self.frame_budget() {
+            if self.run_instruction() {
+                break;
+            }
+        }
+        self.end_frame();
+    }
+
+    // Runs a single instruction, ending the frame if it was the last one in it
+    pub fn step(&mut self) {
+        if self.run_instruction() || self.frame_cycles >= self.frame_budget() {
+            self.end_frame();
+        }
+    }
+
+    // Returns whether the instruction has to wait for the next frame, which sprite draws did
+    // on the VIP as they waited for the display interrupt. A halted processor waits too,
+    // checking the keys once a frame rather than spinning through the frame's instructions.
+    fn run_instruction(&mut self) -> bool {
+        if self.halt.is_some() {
+            self.update_halt();
+            if self.halt.is_some() {
+                return true;
+            }
+        }
+
+        let pc = self.PC;
+        let Some(opcode) = self.opcode_at(pc) else {
+            self.halt = Some(Halt::Error(ProcessorError::EndOfMemory { pc }));
+            return true;
+        };
+        self.execute();
+
+        match self.timing {
+            Timing::Uniform => {
+                self.frame_cycles += 1;
+                false
+            }
+            Timing::Vip => {
+                self.frame_cycles += Timing::vip_cycles(opcode, self.PC == pc + 4);
+                opcode >> 12 == 0xD
+            }
+        }
+    }
+
+    // The instruction at an address, if it isn't past the end of memory
+    pub fn opcode_at(&self, address: usize) -> Option<u16> {
+        let bytes = self.memory.get(address..address + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn update_halt(&mut self) {
+        let pressed_keys = *self.pressed_keys.lock().unwrap();
+        match self.halt {
+            Some(Halt::WaitingForPress { register }) => {
+                if let Some(key) = pressed_keys.iter().rposition(|pressed| *pressed) {
+                    self.halt = Some(Halt::WaitingForRelease { register, key });
+                    if self.quirks.key_press {
+                        self.V_REGS[register] = key as u8;
+                        self.halt = None;
+                    }
+                }
+            }
+            Some(Halt::WaitingForRelease { register, key }) if !pressed_keys[key] => {
+                self.V_REGS[register] = key as u8;
+                self.halt = None;
+            }
+            _ => {}
+        }
+    }
+
+    fn frame_budget(&self) -> u32 {
+        match self.timing {
+            Timing::Uniform => self.instructions_per_frame,
+            Timing::Vip => VIP_CYCLES_PER_FRAME,
+        }
+    }
+
+    // Cycles past the end of the frame are carried over, as the VIP's interrupt would have
+    // landed partway through the instruction
+    fn end_frame(&mut self) {
+        self.frame_cycles = self.frame_cycles.saturating_sub(self.frame_budget());
+        self.tick_timers();
+
+        // The program can write to display memory without drawing, so it's shown every frame
+        if self.memory_map == MemoryMap::Vip {
+            self.render()
+                .expect("Failed to render pixel buffer at the end of a frame");
+        }
+    }
+
+    fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+    }
+
+    // Draws the display onto the screen, if there is one attached. Under the VIP's memory map
+    // the display is read out of memory first, one bit per pixel with the leftmost on top.
+    pub fn render(&mut self) -> Result<(), pixels::Error> {
+        if self.memory_map == MemoryMap::Vip {
+            self.display_from_memory();
+        }
+
+        match &self.screen {
+            Some(screen) => screen
+                .lock()
+                .unwrap()
+                .draw(&self.display, self.color_board.as_ref()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn display_from_memory(&mut self) {
+        let layout = self.vip_layout();
+        let bitmap = &self.memory[layout.display_start..layout.display_start + layout.display_size];
+        for (i, pixel) in self.display.iter_mut().enumerate() {
+            *pixel = if bitmap[i / 8] & (0x80 >> (i % 8)) != 0 {
+                PixelState::On
+            } else {
+                PixelState::Off
+            };
+        }
+    }
+
+    pub fn display_to_memory(&mut self) {
+        let layout = self.vip_layout();
+        let bitmap =
+            &mut self.memory[layout.display_start..layout.display_start + layout.display_size];
+        bitmap.fill(0);
+        for (i, pixel) in self.display.iter().enumerate() {
+            if *pixel == PixelState::On {
+                bitmap[i / 8] |= 0x80 >> (i % 8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A processor with nothing pressed and the program at 0x200
+    fn processor_with(program: &[u16]) -> Processor {
+        let mut processor = Processor::new(Arc::new(Mutex::new([false; 16])));
+        for (i, instruction) in program.iter().enumerate() {
+            let address = 0x200 + i * 2;
+            processor.memory[address..address + 2].copy_from_slice(&instruction.to_be_bytes());
+        }
+        processor
+    }
+
+    #[test]
+    fn running_off_the_end_of_memory() {
+        let mut processor = processor_with(&[0x1FFE]);
+        processor.memory[0xFFE..].copy_from_slice(&[0x60, 0x01]);
+        for _ in 0..3 {
+            processor.step();
+        }
+
+        assert_eq!(processor.V_REGS[0], 1);
+        assert_eq!(
+            processor.halt,
+            Some(Halt::Error(ProcessorError::EndOfMemory { pc: 0x1000 }))
+        );
+    }
+
+    #[test]
+    fn timers_tick_once_a_frame() {
+        let mut processor = processor_with(&[0x1200]);
+        processor.instructions_per_frame = 5;
+        processor.delay_timer = 10;
+        processor.sound_timer = 1;
+
+        processor.run_frame();
+        assert_eq!((processor.delay_timer, processor.sound_timer), (9, 0));
+
+        for _ in 0..4 {
+            processor.step();
+        }
+        assert_eq!(processor.delay_timer, 9);
+        processor.step();
+        assert_eq!(processor.delay_timer, 8);
+
+        for _ in 0..20 {
+            processor.run_frame();
+        }
+        assert_eq!((processor.delay_timer, processor.sound_timer), (0, 0));
+    }
+
+    #[test]
+    fn fx0a_waits_for_the_key_to_be_released() {
+        let mut processor = processor_with(&[0xF30A, 0x1202]);
+        processor.step();
+        assert_eq!(processor.halt, Some(Halt::WaitingForPress { register: 3 }));
+
+        processor.pressed_keys.lock().unwrap()[7] = true;
+        processor.step();
+        processor.step();
+        assert_eq!(
+            processor.halt,
+            Some(Halt::WaitingForRelease {
+                register: 3,
+                key: 7
+            })
+        );
+        assert_eq!(processor.V_REGS[3], 0);
+
+        processor.pressed_keys.lock().unwrap()[7] = false;
+        processor.step();
+        assert_eq!(processor.halt, None);
+        assert_eq!(processor.V_REGS[3], 7);
+        assert_eq!(processor.PC, 0x202);
+    }
+
+    #[test]
+    fn fx0a_returns_on_press_with_the_key_press_quirk() {
+        let mut processor = processor_with(&[0xF30A, 0x1202]);
+        processor.quirks.key_press = true;
+        processor.step();
+
+        processor.pressed_keys.lock().unwrap()[7] = true;
+        processor.step();
+        assert_eq!(processor.halt, None);
+        assert_eq!(processor.V_REGS[3], 7);
+    }
+
+    #[test]
+    fn stack_overflow_at_the_configured_depth() {
+        // Calls itself forever
+        let mut processor = processor_with(&[0x2200]);
+        processor.set_stack_depth(12);
+        for _ in 0..12 {
+            processor.step();
+        }
+        assert_eq!(processor.halt, None);
+        assert_eq!(processor.call_stack().len(), 12);
+
+        processor.step();
+        assert_eq!(
+            processor.halt,
+            Some(Halt::Error(ProcessorError::StackOverflow {
+                pc: 0x200,
+                depth: 12
+            }))
+        );
+    }
+
+    #[test]
+    fn stack_underflow() {
+        let mut processor = processor_with(&[0x2204, 0x00EE, 0x00EE]);
+        processor.step();
+        processor.step();
+        assert_eq!(processor.PC, 0x202);
+        assert_eq!(processor.halt, None);
+
+        processor.step();
+        assert_eq!(
+            processor.halt,
+            Some(Halt::Error(ProcessorError::StackUnderflow { pc: 0x202 }))
+        );
+    }
+
+    fn chip8x_with(program: &[u16]) -> Processor {
+        let mut processor = processor_with(program);
+        processor.platform = Platform::Chip8X;
+        processor.color_board = Some(ColorBoard::default());
+        processor
+    }
+
+    #[test]
+    fn chip8x_background_and_zones() {
+        // 02A0 twice, then BXY2 and BXY0 with V0 = 0A, V1 = 03 and blue in V2
+        let mut processor = chip8x_with(&[
+            0x02A0, 0x02A0, 0x600A, 0x6103, 0x6202, 0xB022, 0x6212, 0xB020,
+        ]);
+        for _ in 0..6 {
+            processor.step();
+        }
+        let board = processor.color_board.clone().unwrap();
+        let off = board.color(0, PixelState::Off);
+        assert_eq!(off, [0x00, 0x80, 0x00]);
+        let blue = [0x00, 0x00, 0xff];
+        let at = |board: &ColorBoard, x: usize, y: usize| board.color(y * 64 + x, PixelState::On);
+        assert_eq!(at(&board, 8, 3), blue);
+        assert_eq!(at(&board, 15, 4), blue);
+        assert_eq!(at(&board, 16, 3), [0xff, 0x00, 0x00]);
+        assert_eq!(at(&board, 8, 5), [0xff, 0x00, 0x00]);
+
+        // BXY0 with V0 = 0A is 11 columns from the left edge, clipped to the 8 there are, and
+        // V1 = 03 is 4 blocks of 4 rows from the top
+        for _ in 0..2 {
+            processor.step();
+        }
+        let board = processor.color_board.clone().unwrap();
+        assert_eq!(at(&board, 0, 0), blue);
+        assert_eq!(at(&board, 63, 15), blue);
+        assert_eq!(at(&board, 0, 16), [0xff, 0x00, 0x00]);
+    }
+
+    #[test]
+    fn chip8x_instructions() {
+        // 5XY1 adds nibbles without carrying, FXF8 sets the tone and FXFB reads nothing
+        let mut processor = chip8x_with(&[0x60F9, 0x6118, 0x5011, 0x62FF, 0xF2F8, 0x63AA, 0xF3FB]);
+        for _ in 0..7 {
+            processor.step();
+        }
+        assert_eq!(processor.V_REGS[0], 0x01);
+        assert_eq!(processor.tone_frequency, Some(27535.0 / 256.0));
+        assert_eq!(processor.V_REGS[3], 0);
+        assert_eq!(processor.halt, None);
+
+        // Without the colour board it's 5XY0, as the VIP's interpreter ignored the last nibble
+        let mut processor = processor_with(&[0x5011]);
+        processor.step();
+        assert_eq!(processor.PC, 0x204);
+    }
 }
@@ -0,0 +1,268 @@
+// Adapted from the pixels crate's ScalingRenderer (https://docs.rs/pixels/latest/pixels/struct.ScalingRenderer.html),
+// because it only ever scales by whole numbers and leaves a large border on most window sizes
+use pixels::wgpu::{self, util::DeviceExt};
+use pixels::Pixels;
+
+/// Renders a texture as large as the surface allows while keeping its aspect ratio,
+/// letterboxing whatever space is left over.
+#[derive(Debug)]
+pub struct FitRenderer {
+    vertex_buffer: wgpu::Buffer,
+    uniform_buffer: wgpu::Buffer,
+    bind_group: wgpu::BindGroup,
+    render_pipeline: wgpu::RenderPipeline,
+    clear_color: Option<wgpu::Color>,
+    width: f32,
+    height: f32,
+    clip_rect: (u32, u32, u32, u32),
+}
+
+impl FitRenderer {
+    // Renders the pixels buffer, clearing the surface first
+    pub fn for_pixels(pixels: &Pixels, surface_size: (u32, u32), clear_color: wgpu::Color) -> Self {
+        FitRenderer::new(
+            pixels.device(),
+            pixels.texture(),
+            pixels.render_texture_format(),
+            surface_size,
+            Some(clear_color),
+        )
+    }
+
+    // Without a clear color the texture is blended on top of whatever was rendered before it
+    pub fn new(
+        device: &wgpu::Device,
+        texture: &wgpu::Texture,
+        render_texture_format: wgpu::TextureFormat,
+        surface_size: (u32, u32),
+        clear_color: Option<wgpu::Color>,
+    ) -> Self {
+        let texture_extent = texture.size();
+        let texture_view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let shader = wgpu::include_wgsl!("shaders/fit.wgsl");
+        let module = device.create_shader_module(shader);
+
+        // Create a texture sampler with nearest neighbor
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("fit_renderer_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            lod_min_clamp: 0.0,
+            lod_max_clamp: 1.0,
+            compare: None,
+            anisotropy_clamp: 1,
+            border_color: None,
+        });
+
+        // One full-screen triangle, two f32 coordinates per vertex
+        let vertex_data: [f32; 6] = [-1.0, -1.0, 3.0, -1.0, -1.0, 3.0];
+        let vertex_bytes = FitRenderer::as_bytes(&vertex_data);
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fit_renderer_vertex_buffer"),
+            contents: &vertex_bytes,
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: 8,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[wgpu::VertexAttribute {
+                format: wgpu::VertexFormat::Float32x2,
+                offset: 0,
+                shader_location: 0,
+            }],
+        };
+
+        // Create uniform buffer
+        let (transform, clip_rect) = FitRenderer::fit_matrix(
+            (texture_extent.width as f32, texture_extent.height as f32),
+            (surface_size.0 as f32, surface_size.1 as f32),
+        );
+        let transform_bytes = FitRenderer::as_bytes(&transform);
+        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("fit_renderer_matrix_uniform_buffer"),
+            contents: &transform_bytes,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // Create bind group
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("fit_renderer_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        multisampled: false,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: wgpu::BufferSize::new(transform_bytes.len() as u64),
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("fit_renderer_bind_group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        // Create pipeline
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("fit_renderer_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("fit_renderer_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &module,
+                entry_point: "vs_main",
+                buffers: &[vertex_buffer_layout],
+            },
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            fragment: Some(wgpu::FragmentState {
+                module: &module,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: render_texture_format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            multiview: None,
+        });
+
+        FitRenderer {
+            vertex_buffer,
+            uniform_buffer,
+            bind_group,
+            render_pipeline,
+            clear_color,
+            width: texture_extent.width as f32,
+            height: texture_extent.height as f32,
+            clip_rect,
+        }
+    }
+
+    /// Draw the texture to the render target.
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, render_target: &wgpu::TextureView) {
+        let mut rpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("fit_renderer_render_pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: render_target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: match self.clear_color {
+                        Some(color) => wgpu::LoadOp::Clear(color),
+                        None => wgpu::LoadOp::Load,
+                    },
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        rpass.set_pipeline(&self.render_pipeline);
+        rpass.set_bind_group(0, &self.bind_group, &[]);
+        rpass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        rpass.set_scissor_rect(
+            self.clip_rect.0,
+            self.clip_rect.1,
+            self.clip_rect.2,
+            self.clip_rect.3,
+        );
+        rpass.draw(0..3, 0..1);
+    }
+
+    pub fn resize(&mut self, queue: &wgpu::Queue, width: u32, height: u32) {
+        let (transform, clip_rect) =
+            FitRenderer::fit_matrix((self.width, self.height), (width as f32, height as f32));
+        queue.write_buffer(&self.uniform_buffer, 0, &FitRenderer::as_bytes(&transform));
+        self.clip_rect = clip_rect;
+    }
+
+    // Same as the pixels crate's ScalingMatrix, without rounding the scale down to a whole number
+    fn fit_matrix(
+        texture_size: (f32, f32),
+        screen_size: (f32, f32),
+    ) -> ([f32; 16], (u32, u32, u32, u32)) {
+        let (texture_width, texture_height) = texture_size;
+        let (screen_width, screen_height) = screen_size;
+
+        let scale = (screen_width / texture_width).min(screen_height / texture_height);
+        let scaled_width = texture_width * scale;
+        let scaled_height = texture_height * scale;
+
+        // Create a transformation matrix
+        let sw = scaled_width / screen_width;
+        let sh = scaled_height / screen_height;
+        let tx = (screen_width / 2.0).fract() / screen_width;
+        let ty = (screen_height / 2.0).fract() / screen_height;
+        #[rustfmt::skip]
+        let transform: [f32; 16] = [
+            sw,  0.0, 0.0, 0.0,
+            0.0, sh,  0.0, 0.0,
+            0.0, 0.0, 1.0, 0.0,
+            tx,  ty,  0.0, 1.0,
+        ];
+
+        // Create a clipping rectangle
+        let scaled_width = scaled_width.min(screen_width);
+        let scaled_height = scaled_height.min(screen_height);
+        let x = ((screen_width - scaled_width) / 2.0) as u32;
+        let y = ((screen_height - scaled_height) / 2.0) as u32;
+
+        (
+            transform,
+            (
+                x,
+                y,
+                (scaled_width as u32).max(1),
+                (scaled_height as u32).max(1),
+            ),
+        )
+    }
+
+    fn as_bytes(values: &[f32]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|value| value.to_ne_bytes())
+            .collect()
+    }
+}
@@ -0,0 +1,37 @@
+use std::thread;
+use std::time::{Duration, Instant};
+
+// How far behind the schedule can fall before it gives up on catching up
+const MAX_LAG_FRAMES: u32 = 5;
+
+// Paces the CPU thread to one frame at a time, sleeping until the next one is due
+pub struct Scheduler {
+    next_frame: Instant,
+}
+
+impl Scheduler {
+    pub fn new() -> Self {
+        Self {
+            next_frame: Instant::now(),
+        }
+    }
+
+    // Starts the schedule over from now, for after a pause
+    pub fn reset(&mut self) {
+        self.next_frame = Instant::now();
+    }
+
+    // Each frame is due a frame after the last one was due, rather than after it finished,
+    // so oversleeping a little doesn't slowly drift the game behind. After a long stall, like
+    // the window being dragged, the schedule restarts instead of rushing through the backlog.
+    pub fn wait_for_next_frame(&mut self, frame_duration: Duration) {
+        self.next_frame += frame_duration;
+
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else if now - self.next_frame > frame_duration * MAX_LAG_FRAMES {
+            self.next_frame = now;
+        }
+    }
+}
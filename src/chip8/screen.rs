@@ -0,0 +1,142 @@
+use super::color_board::ColorBoard;
+use super::execution::PixelState;
+use super::overlay::Overlay;
+use super::renderer::FitRenderer;
+use pixels::{wgpu::Color, Pixels};
+
+const BUFFER_CHUNK_SIZE: usize = 4;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ScaleMode {
+    Fit,     // As large as the window allows, keeping the aspect ratio
+    Integer, // Largest whole-number multiple that fits, for crisp pixels
+}
+
+// Colours for pixels that are off and on
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Palette {
+    pub off: [u8; 3],
+    pub on: [u8; 3],
+}
+
+impl Default for Palette {
+    fn default() -> Self {
+        Self {
+            off: [0x00, 0x00, 0x00],
+            on: [0xff, 0xff, 0xff],
+        }
+    }
+}
+
+impl Palette {
+    // Parses "#RRGGBB", the # is optional
+    pub fn parse_color(text: &str) -> Option<[u8; 3]> {
+        let hex = text.strip_prefix('#').unwrap_or(text);
+        if hex.len() != 6 || !hex.is_ascii() {
+            return None;
+        }
+
+        let mut color = [0; 3];
+        for (i, channel) in color.iter_mut().enumerate() {
+            *channel = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(color)
+    }
+}
+
+#[derive(Debug)]
+pub struct Screen {
+    pub pixels: Pixels,
+    pub scale_mode: ScaleMode,
+    pub palette: Palette,
+    pub overlay: Overlay,
+    fit_renderer: FitRenderer,
+    surface_size: (u32, u32),
+}
+
+impl Screen {
+    pub fn new(mut pixels: Pixels, surface_size: (u32, u32), scale_mode: ScaleMode) -> Self {
+        pixels.clear_color(Color::BLACK);
+        let fit_renderer = FitRenderer::for_pixels(&pixels, surface_size, Color::BLACK);
+        let overlay = Overlay::new(&pixels, surface_size);
+
+        Self {
+            pixels,
+            scale_mode,
+            palette: Palette::default(),
+            overlay,
+            fit_renderer,
+            surface_size,
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // Minimizing reports a zero sized window, which the surface can't be configured with
+        if width == 0 || height == 0 {
+            return;
+        }
+
+        self.pixels
+            .resize_surface(width, height)
+            .expect("Failed to resize surface");
+        self.fit_renderer.resize(self.pixels.queue(), width, height);
+        self.overlay.resize(self.pixels.queue(), width, height);
+        self.surface_size = (width, height);
+        self.render()
+            .expect("Failed to render pixel buffer on resize");
+    }
+
+    // Matches the pixel buffer to the display, which is taller for hires roms. The fit renderer
+    // is bound to the old buffer's texture, so it's rebuilt.
+    pub fn set_resolution(&mut self, width: u32, height: u32) {
+        let texture = self.pixels.texture().size();
+        if (texture.width, texture.height) == (width, height) {
+            return;
+        }
+
+        self.pixels
+            .resize_buffer(width, height)
+            .expect("Failed to resize pixel buffer");
+        self.fit_renderer = FitRenderer::for_pixels(&self.pixels, self.surface_size, Color::BLACK);
+    }
+
+    // Copies the CHIP-8 display into the pixel buffer using the palette, or CHIP-8X's colours
+    // when there are any, then renders it
+    pub fn draw(
+        &mut self,
+        display: &[PixelState],
+        color_board: Option<&ColorBoard>,
+    ) -> Result<(), pixels::Error> {
+        let palette = self.palette;
+        for (i, (pixel, state)) in self
+            .pixels
+            .frame_mut()
+            .chunks_exact_mut(BUFFER_CHUNK_SIZE)
+            .zip(display)
+            .enumerate()
+        {
+            let color = match (color_board, state) {
+                (Some(color_board), _) => color_board.color(i, *state),
+                (None, PixelState::Off) => palette.off,
+                (None, PixelState::On) => palette.on,
+            };
+            pixel[..3].copy_from_slice(&color);
+            pixel[3] = 0xff;
+        }
+
+        self.render()
+    }
+
+    pub fn render(&self) -> Result<(), pixels::Error> {
+        self.pixels.render_with(|encoder, render_target, context| {
+            match self.scale_mode {
+                ScaleMode::Integer => context.scaling_renderer.render(encoder, render_target),
+                ScaleMode::Fit => self.fit_renderer.render(encoder, render_target),
+            }
+            if self.overlay.visible {
+                self.overlay.render(encoder, render_target, &context.queue);
+            }
+            Ok(())
+        })
+    }
+}
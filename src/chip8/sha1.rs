@@ -0,0 +1,100 @@
+// SHA-1 (FIPS 180-4), only used to identify roms so it doesn't need to be fast
+pub struct Sha1 {}
+
+impl Sha1 {
+    pub fn digest(data: &[u8]) -> [u8; 20] {
+        let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+        // Pad with a 1 bit, zeros up to 56 bytes mod 64, then the bit length as a big endian u64
+        let mut message = data.to_vec();
+        message.push(0x80);
+        while message.len() % 64 != 56 {
+            message.push(0);
+        }
+        message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+        for block in message.chunks_exact(64) {
+            let mut w = [0u32; 80];
+            for (i, word) in block.chunks_exact(4).enumerate() {
+                w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+            }
+            for i in 16..80 {
+                w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+            }
+
+            let [mut a, mut b, mut c, mut d, mut e] = h;
+            for (i, word) in w.iter().enumerate() {
+                let (f, k) = match i {
+                    0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                    20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                    40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                    _ => (b ^ c ^ d, 0xCA62C1D6),
+                };
+                let temp = a
+                    .rotate_left(5)
+                    .wrapping_add(f)
+                    .wrapping_add(e)
+                    .wrapping_add(k)
+                    .wrapping_add(*word);
+                e = d;
+                d = c;
+                c = b.rotate_left(30);
+                b = a;
+                a = temp;
+            }
+
+            for (value, add) in h.iter_mut().zip([a, b, c, d, e]) {
+                *value = value.wrapping_add(add);
+            }
+        }
+
+        let mut digest = [0; 20];
+        for (bytes, value) in digest.chunks_exact_mut(4).zip(h) {
+            bytes.copy_from_slice(&value.to_be_bytes());
+        }
+        digest
+    }
+
+    pub fn hex_digest(data: &[u8]) -> String {
+        Sha1::digest(data)
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The test vectors from RFC 3174
+    #[test]
+    fn rfc_3174_vectors() {
+        assert_eq!(
+            Sha1::hex_digest(b"abc"),
+            "a9993e364706816aba3e25717850c26c9cd0d89d"
+        );
+        assert_eq!(
+            Sha1::hex_digest(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            Sha1::hex_digest(&vec![b'a'; 1_000_000]),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+        assert_eq!(
+            Sha1::hex_digest(
+                &b"0123456701234567012345670123456701234567012345670123456701234567".repeat(10)
+            ),
+            "dea356a2cddd90c7a7ecedc5ebb563934f460452"
+        );
+    }
+
+    #[test]
+    fn empty_input() {
+        assert_eq!(
+            Sha1::hex_digest(b""),
+            "da39a3ee5e6b4b0d3255bfef95601890afd80709"
+        );
+    }
+}
@@ -0,0 +1,114 @@
+use super::timing::Timing;
+use std::time::Duration;
+
+const MAX_INSTRUCTIONS_PER_FRAME: u32 = 10000;
+// How many times faster or slower than normal fast-forward and slow motion run
+const FAST_FORWARD_FACTOR: u32 = 4;
+const SLOW_MOTION_FACTOR: u32 = 4;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum SpeedMode {
+    Normal,
+    FastForward,
+    SlowMotion,
+}
+
+// How fast the CPU thread runs, shared with the window so the hotkeys can change it
+#[derive(Clone, Copy, Debug)]
+pub struct Speed {
+    pub instructions_per_frame: u32,
+    pub mode: SpeedMode,
+    pub timing: Timing, // Under VIP timing the instruction costs set the speed instead
+}
+
+impl Speed {
+    pub fn new(instructions_per_frame: u32) -> Self {
+        Self {
+            instructions_per_frame,
+            mode: SpeedMode::Normal,
+            timing: Timing::Uniform,
+        }
+    }
+
+    // Steps by one at low speeds and by about a tenth at high ones, so both are quick to tune
+    pub fn faster(&mut self) {
+        let step = (self.instructions_per_frame / 10).max(1);
+        self.instructions_per_frame =
+            (self.instructions_per_frame + step).min(MAX_INSTRUCTIONS_PER_FRAME);
+    }
+
+    // Steps down by what faster would have stepped up by to get here, so one undoes the other
+    pub fn slower(&mut self) {
+        let step = (self.instructions_per_frame / 11).max(1);
+        self.instructions_per_frame = self.instructions_per_frame.saturating_sub(step).max(1);
+    }
+
+    // Fast-forward and slow motion run whole frames, timers included, more or less often
+    pub fn frame_duration(&self) -> Duration {
+        let frame = Duration::from_secs(1) / 60;
+        match self.mode {
+            SpeedMode::Normal => frame,
+            SpeedMode::FastForward => frame / FAST_FORWARD_FACTOR,
+            SpeedMode::SlowMotion => frame * SLOW_MOTION_FACTOR,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        let speed = match self.timing {
+            Timing::Uniform => format!("{} IPF", self.instructions_per_frame),
+            Timing::Vip => "VIP timing".to_string(),
+        };
+        match self.mode {
+            SpeedMode::Normal => format!("Speed {}", speed),
+            SpeedMode::FastForward => format!("Fast forward x{} ({})", FAST_FORWARD_FACTOR, speed),
+            SpeedMode::SlowMotion => format!("Slow motion x1/{} ({})", SLOW_MOTION_FACTOR, speed),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn faster(instructions_per_frame: u32) -> u32 {
+        let mut speed = Speed::new(instructions_per_frame);
+        speed.faster();
+        speed.instructions_per_frame
+    }
+
+    fn slower(instructions_per_frame: u32) -> u32 {
+        let mut speed = Speed::new(instructions_per_frame);
+        speed.slower();
+        speed.instructions_per_frame
+    }
+
+    #[test]
+    fn step_sizes() {
+        assert_eq!(faster(1), 2);
+        assert_eq!(faster(15), 16);
+        assert_eq!(faster(100), 110);
+        assert_eq!(slower(15), 14);
+        assert_eq!(slower(110), 100);
+        assert_eq!(slower(1000), 910);
+    }
+
+    #[test]
+    fn slower_undoes_faster() {
+        for instructions_per_frame in 1..9000 {
+            assert_eq!(
+                slower(faster(instructions_per_frame)),
+                instructions_per_frame
+            );
+        }
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(slower(1), 1);
+        assert_eq!(slower(0), 1);
+        assert_eq!(
+            faster(MAX_INSTRUCTIONS_PER_FRAME),
+            MAX_INSTRUCTIONS_PER_FRAME
+        );
+    }
+}
@@ -0,0 +1,51 @@
+// The VIP ran at 1.7609 MHz with 8 clock cycles per machine cycle, giving 3668 machine cycles
+// between each 60Hz display interrupt
+pub const VIP_CYCLES_PER_FRAME: u32 = 3668;
+// The interpreter's fetch and decode loop, paid by every instruction on top of its own cost
+const VIP_FETCH_CYCLES: u32 = 40;
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Timing {
+    Uniform, // Every instruction takes the same time, instructions_per_frame of them a frame
+    Vip,     // Instructions cost what they did in the COSMAC VIP's interpreter
+}
+
+impl Timing {
+    // Approximate machine cycles each instruction took on the VIP, from Laurence Scotford's
+    // disassembly of the original interpreter. Costs that depended on the data, like how
+    // many bits of a sprite were set, use a typical value.
+    pub fn vip_cycles(opcode: u16, skipped: bool) -> u32 {
+        let skip = if skipped { 4 } else { 0 };
+        let x = ((opcode >> 8) & 0xF) as u32;
+        let n = (opcode & 0xF) as u32;
+
+        let cycles = match opcode >> 12 {
+            0x0 => match opcode {
+                0x00E0 => 3078,
+                0x00EE => 10,
+                _ => 0,
+            },
+            0x1 => 12,
+            0x2 => 26,
+            0x3 | 0x4 => 10 + skip,
+            0x5 | 0x9 => 14 + skip,
+            0x6 => 6,
+            0x7 => 10,
+            0x8 => 44,
+            0xA => 12,
+            0xB => 22,
+            0xC => 36,
+            0xD => 68 + n * 46,
+            0xE => 14 + skip,
+            0xF => match opcode & 0xFF {
+                0x1E | 0x29 => 16,
+                0x33 => 120,
+                0x55 | 0x65 => 14 + 14 * (x + 1),
+                _ => 10,
+            },
+            _ => 0,
+        };
+
+        VIP_FETCH_CYCLES + cycles
+    }
+}
@@ -0,0 +1,526 @@
+// Minimal parser for the subset of TOML used by our config files: tables, dotted keys,
+// strings, integers, floats, booleans and arrays (which may span several lines).
+// Inline tables and arrays of tables are not supported.
+use std::collections::BTreeMap;
+use std::fmt;
+
+pub type TomlTable = BTreeMap<String, TomlValue>;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TomlValue {
+    String(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Array(Vec<TomlValue>),
+    Table(TomlTable),
+}
+
+impl TomlValue {
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            TomlValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i64> {
+        match self {
+            TomlValue::Integer(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    // Integers are accepted too, so "volume = 1" works as well as "volume = 1.0"
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            TomlValue::Float(value) => Some(*value),
+            TomlValue::Integer(value) => Some(*value as f64),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            TomlValue::Boolean(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<TomlValue>> {
+        match self {
+            TomlValue::Array(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    pub fn as_table(&self) -> Option<&TomlTable> {
+        match self {
+            TomlValue::Table(value) => Some(value),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct TomlError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for TomlError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+pub struct Toml {}
+
+impl Toml {
+    pub fn parse(text: &str) -> Result<TomlTable, TomlError> {
+        let mut parser = Parser::new(text);
+        let mut root = TomlTable::new();
+        let mut current_path: Vec<String> = Vec::new();
+
+        loop {
+            parser.skip_whitespace_and_comments();
+            match parser.peek() {
+                None => break,
+                Some('[') => {
+                    parser.pos += 1;
+                    if parser.peek() == Some('[') {
+                        return Err(parser.error("arrays of tables are not supported"));
+                    }
+                    parser.skip_spaces();
+                    current_path = parser.parse_key_path()?;
+                    parser.skip_spaces();
+                    parser.expect(']')?;
+                    Toml::table_at(&mut root, &current_path).map_err(|e| parser.error(&e))?;
+                }
+                Some(_) => {
+                    let mut path = parser.parse_key_path()?;
+                    parser.skip_spaces();
+                    parser.expect('=')?;
+                    parser.skip_spaces();
+                    let value = parser.parse_value()?;
+
+                    let key = path.pop().unwrap();
+                    path.splice(0..0, current_path.iter().cloned());
+                    let table = Toml::table_at(&mut root, &path).map_err(|e| parser.error(&e))?;
+                    if table.contains_key(&key) {
+                        return Err(parser.error(&format!("duplicate key \"{}\"", key)));
+                    }
+                    table.insert(key, value);
+                }
+            }
+            parser.expect_line_end()?;
+        }
+
+        Ok(root)
+    }
+
+    // Copies every value from overrides into base, merging tables that exist in both
+    pub fn merge(base: &mut TomlTable, overrides: TomlTable) {
+        for (key, value) in overrides {
+            match (base.get_mut(&key), value) {
+                (Some(TomlValue::Table(base_table)), TomlValue::Table(override_table)) => {
+                    Toml::merge(base_table, override_table)
+                }
+                (_, value) => {
+                    base.insert(key, value);
+                }
+            }
+        }
+    }
+
+    // Sets one value in the text of a TOML file, leaving everything else including comments
+    // as it was. Only meant for values written on a single line.
+    pub fn set_value(
+        text: &str,
+        section: &[String],
+        key: &str,
+        value: &TomlValue,
+    ) -> Result<String, String> {
+        let new_line = format!("{} = {}", Toml::format_key(key), Toml::format_value(value)?);
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+
+        // Where a new key goes: after the last key in the section, or right after its header
+        let mut insert_at = if section.is_empty() { Some(0) } else { None };
+        let mut current_path: Vec<String> = Vec::new();
+        for i in 0..lines.len() {
+            let line = lines[i].trim();
+            if let Some(header) = line.strip_prefix('[') {
+                current_path = Parser::new(header).parse_key_path().unwrap_or_default();
+                if current_path == section {
+                    insert_at = Some(i + 1);
+                }
+                continue;
+            }
+            if current_path != section || line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            insert_at = Some(i + 1);
+            if Parser::new(line).parse_key_path().ok() == Some(vec![key.to_string()]) {
+                lines[i] = new_line;
+                return Ok(lines.join("\n") + "\n");
+            }
+        }
+
+        match insert_at {
+            Some(i) => lines.insert(i, new_line),
+            None => {
+                if !lines.is_empty() {
+                    lines.push(String::new());
+                }
+                let header: Vec<String> = section.iter().map(|key| Toml::format_key(key)).collect();
+                lines.push(format!("[{}]", header.join(".")));
+                lines.push(new_line);
+            }
+        }
+        Ok(lines.join("\n") + "\n")
+    }
+
+    pub fn format_value(value: &TomlValue) -> Result<String, String> {
+        let text = match value {
+            TomlValue::String(text) => Toml::format_string(text),
+            TomlValue::Integer(number) => number.to_string(),
+            // Debug formatting always includes a decimal point, so it reads back as a float
+            TomlValue::Float(number) => format!("{:?}", number),
+            TomlValue::Boolean(boolean) => boolean.to_string(),
+            TomlValue::Array(values) => {
+                let values: Vec<String> = values
+                    .iter()
+                    .map(Toml::format_value)
+                    .collect::<Result<_, _>>()?;
+                format!("[{}]", values.join(", "))
+            }
+            TomlValue::Table(_) => return Err("inline tables are not supported".to_string()),
+        };
+        Ok(text)
+    }
+
+    // Keys are left bare when they can be, like "volume", and quoted otherwise
+    fn format_key(key: &str) -> String {
+        let bare = !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if bare {
+            key.to_string()
+        } else {
+            Toml::format_string(key)
+        }
+    }
+
+    fn format_string(text: &str) -> String {
+        let mut quoted = String::from('"');
+        for c in text.chars() {
+            match c {
+                '"' => quoted.push_str("\\\""),
+                '\\' => quoted.push_str("\\\\"),
+                '\n' => quoted.push_str("\\n"),
+                '\t' => quoted.push_str("\\t"),
+                _ => quoted.push(c),
+            }
+        }
+        quoted.push('"');
+        quoted
+    }
+
+    // Follows a path of keys from the root, creating any tables that don't exist yet
+    fn table_at<'a>(root: &'a mut TomlTable, path: &[String]) -> Result<&'a mut TomlTable, String> {
+        let mut table = root;
+        for key in path {
+            let entry = table
+                .entry(key.clone())
+                .or_insert_with(|| TomlValue::Table(TomlTable::new()));
+            table = match entry {
+                TomlValue::Table(inner) => inner,
+                _ => return Err(format!("\"{}\" is already defined as a value", key)),
+            };
+        }
+        Ok(table)
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+        }
+    }
+
+    fn error(&self, message: &str) -> TomlError {
+        TomlError {
+            line: self.line,
+            message: message.to_string(),
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek();
+        if let Some(c) = c {
+            self.pos += 1;
+            if c == '\n' {
+                self.line += 1;
+            }
+        }
+        c
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), TomlError> {
+        match self.next() {
+            Some(c) if c == expected => Ok(()),
+            Some(c) => Err(self.error(&format!("expected '{}', found '{}'", expected, c))),
+            None => Err(self.error(&format!("expected '{}', found end of file", expected))),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ') | Some('\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.pos += 1;
+            }
+        }
+    }
+
+    fn skip_whitespace_and_comments(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            match self.peek() {
+                Some('\n') | Some('\r') => {
+                    self.next();
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn expect_line_end(&mut self) -> Result<(), TomlError> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek() == Some('\r') {
+            self.pos += 1;
+        }
+        match self.next() {
+            None | Some('\n') => Ok(()),
+            Some(c) => Err(self.error(&format!("unexpected '{}' after value", c))),
+        }
+    }
+
+    fn parse_key_path(&mut self) -> Result<Vec<String>, TomlError> {
+        let mut path = vec![self.parse_key()?];
+        loop {
+            self.skip_spaces();
+            if self.peek() != Some('.') {
+                return Ok(path);
+            }
+            self.pos += 1;
+            self.skip_spaces();
+            path.push(self.parse_key()?);
+        }
+    }
+
+    fn parse_key(&mut self) -> Result<String, TomlError> {
+        match self.peek() {
+            Some('"') => self.parse_basic_string(),
+            Some('\'') => self.parse_literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        key.push(c);
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+                if key.is_empty() {
+                    return Err(self.error("expected a key"));
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn parse_value(&mut self) -> Result<TomlValue, TomlError> {
+        match self.peek() {
+            Some('"') => Ok(TomlValue::String(self.parse_basic_string()?)),
+            Some('\'') => Ok(TomlValue::String(self.parse_literal_string()?)),
+            Some('[') => self.parse_array(),
+            Some('{') => Err(self.error("inline tables are not supported")),
+            Some(_) => self.parse_bare_value(),
+            None => Err(self.error("expected a value, found end of file")),
+        }
+    }
+
+    fn parse_array(&mut self) -> Result<TomlValue, TomlError> {
+        self.expect('[')?;
+        let mut values = Vec::new();
+        loop {
+            self.skip_whitespace_and_comments();
+            if self.peek() == Some(']') {
+                self.pos += 1;
+                return Ok(TomlValue::Array(values));
+            }
+            values.push(self.parse_value()?);
+            self.skip_whitespace_and_comments();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(TomlValue::Array(values)),
+                _ => return Err(self.error("expected ',' or ']' in array")),
+            }
+        }
+    }
+
+    fn parse_basic_string(&mut self) -> Result<String, TomlError> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some('"') => return Ok(value),
+                Some('\\') => match self.next() {
+                    Some('n') => value.push('\n'),
+                    Some('t') => value.push('\t'),
+                    Some('r') => value.push('\r'),
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| self.next()).collect();
+                        let c = u32::from_str_radix(&hex, 16)
+                            .ok()
+                            .and_then(char::from_u32)
+                            .ok_or_else(|| self.error("invalid unicode escape"))?;
+                        value.push(c);
+                    }
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_literal_string(&mut self) -> Result<String, TomlError> {
+        self.expect('\'')?;
+        let mut value = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err(self.error("unterminated string")),
+                Some('\'') => return Ok(value),
+                Some(c) => value.push(c),
+            }
+        }
+    }
+
+    fn parse_bare_value(&mut self) -> Result<TomlValue, TomlError> {
+        let mut word = String::new();
+        while let Some(c) = self.peek() {
+            if c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '+' | '.') {
+                word.push(c);
+                self.pos += 1;
+            } else {
+                break;
+            }
+        }
+
+        let number = word.replace('_', "");
+        let value = match word.as_str() {
+            "true" => Some(TomlValue::Boolean(true)),
+            "false" => Some(TomlValue::Boolean(false)),
+            _ if number.starts_with("0x") => i64::from_str_radix(&number[2..], 16)
+                .ok()
+                .map(TomlValue::Integer),
+            _ if number.contains(['.', 'e', 'E']) => number.parse().ok().map(TomlValue::Float),
+            _ => number.parse().ok().map(TomlValue::Integer),
+        };
+
+        value.ok_or_else(|| self.error(&format!("invalid value \"{}\"", word)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str) -> String {
+        name.to_string()
+    }
+
+    #[test]
+    fn quoted_keys_and_arrays() {
+        let text = "# Settings\n[rom.\"0a1b\"]\nspeed = 15 # per frame\npalette = [\"#000000\",\n  \"#ffffff\"]\n";
+        let table = Toml::parse(text).unwrap();
+        let rom = table["rom"].as_table().unwrap()["0a1b"].as_table().unwrap();
+
+        assert_eq!(rom["speed"], TomlValue::Integer(15));
+        assert_eq!(
+            rom["palette"],
+            TomlValue::Array(vec![
+                TomlValue::String(key("#000000")),
+                TomlValue::String(key("#ffffff"))
+            ])
+        );
+    }
+
+    #[test]
+    fn replacing_a_value_keeps_the_rest() {
+        let text = "# Settings\nvolume = 0.5 # quiet\n\n[rom.\"0a1b\"]\nspeed = 15\n";
+        let section = [key("rom"), key("0a1b")];
+        let text = Toml::set_value(text, &section, "speed", &TomlValue::Integer(30)).unwrap();
+
+        assert_eq!(
+            text,
+            "# Settings\nvolume = 0.5 # quiet\n\n[rom.\"0a1b\"]\nspeed = 30\n"
+        );
+    }
+
+    #[test]
+    fn new_values_round_trip() {
+        let palette = TomlValue::Array(vec![
+            TomlValue::String(key("#000000")),
+            TomlValue::String(key("say \"hi\"")),
+        ]);
+        let text = Toml::set_value("volume = 0.5\n", &[], "muted", &TomlValue::Boolean(true));
+        let text = Toml::set_value(
+            &text.unwrap(),
+            &[key("rom"), key("a b")],
+            "palette",
+            &palette,
+        );
+        let table = Toml::parse(&text.unwrap()).unwrap();
+
+        assert_eq!(table["volume"], TomlValue::Float(0.5));
+        assert_eq!(table["muted"], TomlValue::Boolean(true));
+        assert_eq!(
+            table["rom"].as_table().unwrap()["a b"].as_table().unwrap()["palette"],
+            palette
+        );
+    }
+
+    #[test]
+    fn inline_tables_are_an_error() {
+        let table = TomlValue::Table(TomlTable::new());
+        assert!(Toml::set_value("", &[], "keys", &table).is_err());
+    }
+}
@@ -1,3 +1,6 @@
+use std::env;
+use std::path::PathBuf;
+
 pub struct Utils {}
 
 impl Utils {
@@ -8,4 +11,14 @@ impl Utils {
         }
         u16::from_str_radix(&num, 16).expect("Failed to convert hex number")
     }
+
+    // Follows the XDG convention, falling back to ~/.config (or %APPDATA% on Windows)
+    pub fn config_dir() -> Option<PathBuf> {
+        let base = env::var_os("XDG_CONFIG_HOME")
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+            .or_else(|| env::var_os("APPDATA").map(PathBuf::from))?;
+        Some(base.join("chip8-interpreter"))
+    }
 }
@@ -0,0 +1,256 @@
+use super::cdp1802::{Bus, Cdp1802};
+use super::execution::PixelState;
+use super::timing::VIP_CYCLES_PER_FRAME;
+
+// The COSMAC VIP itself, rather than a CHIP-8 interpreter: a CDP1802, the CDP1861 "Pixie" video
+// chip, the hex keypad and 4K of RAM, running whichever interpreter is loaded at 0x000 just as
+// the real machine did.
+
+pub const VIP_MEMORY_SIZE: usize = 0x1000;
+// Where the interpreter has to fit, below the rom at 0x200
+pub const VIP_INTERPRETER_SIZE: usize = 0x200;
+
+// The 1861 draws 262 lines a frame, 14 machine cycles each. It interrupts two lines before the
+// 128 lines of the picture and steals 8 cycles at the start of each for DMA. EF1 is raised for
+// the 4 lines before the picture starts and before it ends.
+const LINES: u32 = 262;
+const LINE_CYCLES: u32 = VIP_CYCLES_PER_FRAME / LINES;
+const INTERRUPT_LINE: u32 = 78;
+const DISPLAY_START_LINE: u32 = 80;
+const DISPLAY_END_LINE: u32 = 208;
+const DMA_CYCLES: u32 = 8;
+const DISPLAY_FLAG_LINES: u32 = 4;
+
+// The interrupt routine the VIP's interpreter points R1 at, 0x8146 in the monitor ROM. This
+// isn't the original ROM but a stand-in that does the same job: it points R0 at the display
+// page in RB for the DMA, waits out the picture, then counts down the timers in R8.1 and R8.0,
+// sounding the tone while R8.0 runs.
+const MONITOR_START: u16 = 0x8000;
+const INTERRUPT_ROUTINE_START: u16 = 0x8144;
+const INTERRUPT_ROUTINE: [u8; 33] = [
+    0x72, 0x70, // 8144: LDXA, RET (exit, restoring D, X and P)
+    0x22, 0x78, 0x22, 0x52, // 8146: DEC 2, SAV, DEC 2, STR 2 (entry, saving T and D)
+    0x9B, 0xB0, 0xF8, 0x00, 0xA0, // 814A: GHI B, PHI 0, LDI 00, PLO 0
+    0x34, 0x4F, 0x3C, 0x51, // 814F: B1 814F, BN1 8151
+    0x98, 0x32, 0x59, 0xFF, 0x01, 0xB8, // 8153: GHI 8, BZ 8159, SMI 01, PHI 8
+    0x88, 0x32, 0x62, 0xFF, 0x01, 0xA8, // 8159: GLO 8, BZ 8162, SMI 01, PLO 8
+    0x7B, 0x30, 0x44, // 815F: SEQ, BR 8144
+    0x7A, 0x30, 0x44, // 8162: REQ, BR 8144
+];
+
+// What the program can reach besides memory. OUT 2 picks a key on the hex keypad and EF3 tells
+// whether it's held. INP 1 turns the display on and OUT 1 turns it off.
+#[derive(Clone, Debug)]
+pub struct VipIo {
+    pub pressed_keys: [bool; 16],
+    selected_key: usize,
+    display_on: bool,
+    display_flag: bool,
+}
+
+impl VipIo {
+    pub fn new(pressed_keys: [bool; 16]) -> Self {
+        Self {
+            pressed_keys,
+            selected_key: 0,
+            display_on: false,
+            display_flag: false,
+        }
+    }
+}
+
+// RAM repeats through the bottom half of the address space and the monitor ROM through the top
+pub struct VipBus<'a> {
+    pub memory: &'a mut [u8],
+    pub io: &'a mut VipIo,
+}
+
+impl Bus for VipBus<'_> {
+    fn read(&self, address: u16) -> u8 {
+        if address >= MONITOR_START {
+            let offset = address.wrapping_sub(INTERRUPT_ROUTINE_START) as usize;
+            INTERRUPT_ROUTINE.get(offset).copied().unwrap_or(0)
+        } else {
+            self.memory[address as usize % self.memory.len()]
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        if address < MONITOR_START {
+            let length = self.memory.len();
+            self.memory[address as usize % length] = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.io.display_on = false,
+            2 => self.io.selected_key = (value & 0xF) as usize,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.io.display_on = true;
+        }
+        0
+    }
+
+    fn flag(&self, flag: u8) -> bool {
+        match flag {
+            1 => self.io.display_flag,
+            3 => self.io.pressed_keys[self.io.selected_key],
+            _ => false,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Vip {
+    cpu: Cdp1802,
+    io: VipIo,
+    overrun: u32, // Cycles the last instruction of a line ran into the next
+}
+
+impl Vip {
+    // Loads the interpreter at 0x000 and starts it the way the monitor ROM hands over to a
+    // program, with R0 as the program counter and R1.1 the last page of RAM, where the
+    // interpreter puts the display
+    pub fn new(memory: &mut [u8], interpreter: &[u8]) -> Self {
+        memory[..interpreter.len()].copy_from_slice(interpreter);
+
+        let mut cpu = Cdp1802 {
+            ie: true,
+            ..Default::default()
+        };
+        cpu.r[1] = ((memory.len() / 0x100 - 1) as u16) << 8;
+        Self {
+            cpu,
+            io: VipIo::new([false; 16]),
+            overrun: 0,
+        }
+    }
+
+    // Runs the machine for one frame, line by line. The picture is read in one go from R0 as
+    // the display starts, 256 bytes of it, rather than a line at a time, so the interrupt
+    // routine doesn't need to be timed to the cycle to repeat each line four times.
+    pub fn run_frame(
+        &mut self,
+        memory: &mut [u8],
+        display: &mut [PixelState],
+        pressed_keys: [bool; 16],
+    ) {
+        self.io.pressed_keys = pressed_keys;
+        let mut bus = VipBus {
+            memory,
+            io: &mut self.io,
+        };
+
+        let mut interrupt = false;
+        for line in 0..LINES {
+            let display_on = bus.io.display_on;
+            bus.io.display_flag = display_on
+                && [DISPLAY_START_LINE, DISPLAY_END_LINE]
+                    .iter()
+                    .any(|end| (end - DISPLAY_FLAG_LINES..*end).contains(&line));
+            if line == INTERRUPT_LINE {
+                interrupt = display_on;
+            }
+
+            let mut cycles = self.overrun;
+            if display_on && (DISPLAY_START_LINE..DISPLAY_END_LINE).contains(&line) {
+                if line == DISPLAY_START_LINE {
+                    Vip::read_display(&mut self.cpu, &bus, display);
+                }
+                self.cpu.idle = false;
+                interrupt = false;
+                cycles += DMA_CYCLES;
+            }
+
+            while cycles < LINE_CYCLES {
+                // The 1861 holds its interrupt request until the picture starts
+                if interrupt && self.cpu.ie {
+                    self.cpu.interrupt();
+                    interrupt = false;
+                    cycles += 1;
+                }
+                cycles += self.cpu.step(&mut bus);
+            }
+            self.overrun = cycles - LINE_CYCLES;
+        }
+    }
+
+    // The tone, which the VIP sounds whenever Q is set
+    pub fn tone(&self) -> bool {
+        self.cpu.q
+    }
+
+    fn read_display(cpu: &mut Cdp1802, bus: &VipBus, display: &mut [PixelState]) {
+        let start = cpu.r[0];
+        for (i, pixel) in display.iter_mut().enumerate() {
+            let byte = bus.read(start.wrapping_add((i / 8) as u16));
+            *pixel = if byte & (0x80 >> (i % 8)) != 0 {
+                PixelState::On
+            } else {
+                PixelState::Off
+            };
+        }
+        cpu.r[0] = start.wrapping_add((display.len() / 8) as u16);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Just enough of an interpreter to use the monitor's interrupt routine: it points R1 at
+    // 0x8146, R2 at a stack, RB.1 at the display page and R8 at the timers, moves the program
+    // counter off R0, which the routine uses for the display, turns the display on and spins
+    const INTERPRETER: [u8; 32] = [
+        0xF8, 0x81, 0xB1, 0xF8, 0x46, 0xA1, // 000: R1 = 8146
+        0xF8, 0x0E, 0xB2, 0xF8, 0xCF, 0xA2, // 006: R2 = 0ECF
+        0xF8, 0x0F, 0xBB, // 00C: RB.1 = 0F
+        0xF8, 0x05, 0xB8, 0xF8, 0x03, 0xA8, // 00F: R8.1 = 05, R8.0 = 03
+        0xF8, 0x00, 0xB3, 0xF8, 0x1C, 0xA3, 0xD3, // 015: R3 = 001C, SEP 3
+        0xE2, 0x69, 0x30, 0x1E, // 01C: SEX 2, INP 1, BR 01E
+    ];
+
+    #[test]
+    fn interrupt_routine_counts_down_the_timers() {
+        let mut memory = vec![0; VIP_MEMORY_SIZE];
+        let mut vip = Vip::new(&mut memory, &INTERPRETER);
+        let mut display = vec![PixelState::Off; 64 * 32];
+
+        let mut frame = |vip: &mut Vip, memory: &mut Vec<u8>| {
+            vip.run_frame(memory, &mut display, [false; 16]);
+            (vip.cpu.r[8], vip.tone())
+        };
+        assert_eq!(frame(&mut vip, &mut memory), (0x0402, true));
+        assert_eq!(frame(&mut vip, &mut memory), (0x0301, true));
+        assert_eq!(frame(&mut vip, &mut memory), (0x0200, true));
+        assert_eq!(frame(&mut vip, &mut memory), (0x0100, false));
+        assert_eq!(frame(&mut vip, &mut memory), (0x0000, false));
+        assert_eq!(frame(&mut vip, &mut memory), (0x0000, false));
+
+        // Back in the interpreter's loop, with its stack as it was
+        assert_eq!((vip.cpu.p, vip.cpu.r[3]), (3, 0x001E));
+        assert_eq!(vip.cpu.r[2], 0x0ECF);
+        assert!(vip.cpu.ie);
+    }
+
+    #[test]
+    fn display_is_read_from_the_page_in_rb() {
+        let mut memory = vec![0; VIP_MEMORY_SIZE];
+        let mut vip = Vip::new(&mut memory, &INTERPRETER);
+        memory[0xF00] = 0xF0;
+        memory[0xFFF] = 0x01;
+
+        let mut display = vec![PixelState::Off; 64 * 32];
+        vip.run_frame(&mut memory, &mut display, [false; 16]);
+
+        let on = |pixel: &PixelState| *pixel == PixelState::On;
+        let lit: Vec<usize> = (0..display.len()).filter(|i| on(&display[*i])).collect();
+        assert_eq!(lit, [0, 1, 2, 3, 64 * 32 - 1]);
+    }
+}
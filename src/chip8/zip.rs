@@ -0,0 +1,178 @@
+use super::inflate::Inflate;
+
+const LOCAL_HEADER_SIGNATURE: u32 = 0x04034b50;
+const CENTRAL_HEADER_SIGNATURE: u32 = 0x02014b50;
+const END_OF_DIRECTORY_SIGNATURE: u32 = 0x06054b50;
+const END_OF_DIRECTORY_SIZE: usize = 22;
+
+#[derive(Clone, Debug)]
+pub struct ZipEntry {
+    pub name: String,
+    compression: u16,
+    compressed_size: usize,
+    local_header: usize, // Offset of the entry's local header
+}
+
+// Reads files out of zip archives. Only stored and deflated entries are supported, which is
+// all that zip tools produce by default.
+pub struct Zip {}
+
+impl Zip {
+    pub fn is_zip(data: &[u8]) -> bool {
+        data.len() >= 4 && Zip::u32_at(data, 0) == Some(LOCAL_HEADER_SIGNATURE)
+    }
+
+    // Lists the files in the archive from its central directory, leaving out directories
+    pub fn entries(data: &[u8]) -> Result<Vec<ZipEntry>, String> {
+        // The end record sits at the very end, unless the archive has a comment after it
+        let end = (0..=data.len().saturating_sub(END_OF_DIRECTORY_SIZE))
+            .rev()
+            .find(|offset| Zip::u32_at(data, *offset) == Some(END_OF_DIRECTORY_SIGNATURE))
+            .ok_or("not a zip archive")?;
+        let count = Zip::u16_at(data, end + 10).ok_or("corrupt zip archive")? as usize;
+        let mut offset = Zip::u32_at(data, end + 16).ok_or("corrupt zip archive")? as usize;
+
+        let mut entries = Vec::new();
+        for _ in 0..count {
+            if Zip::u32_at(data, offset) != Some(CENTRAL_HEADER_SIGNATURE) {
+                return Err("corrupt zip directory".to_string());
+            }
+            let field = |at: usize| Zip::u16_at(data, offset + at).ok_or("corrupt zip directory");
+            let compression = field(10)?;
+            let name_length = field(28)? as usize;
+            let extra_length = field(30)? as usize;
+            let comment_length = field(32)? as usize;
+            let compressed_size =
+                Zip::u32_at(data, offset + 20).ok_or("corrupt zip directory")? as usize;
+            let local_header =
+                Zip::u32_at(data, offset + 42).ok_or("corrupt zip directory")? as usize;
+            let name = data
+                .get(offset + 46..offset + 46 + name_length)
+                .ok_or("corrupt zip directory")?;
+
+            let name = String::from_utf8_lossy(name).to_string();
+            if !name.ends_with('/') {
+                entries.push(ZipEntry {
+                    name,
+                    compression,
+                    compressed_size,
+                    local_header,
+                });
+            }
+            offset += 46 + name_length + extra_length + comment_length;
+        }
+
+        Ok(entries)
+    }
+
+    // Deflated entries are only expanded up to the limit
+    pub fn extract(data: &[u8], entry: &ZipEntry, limit: usize) -> Result<Vec<u8>, String> {
+        let header = entry.local_header;
+        if Zip::u32_at(data, header) != Some(LOCAL_HEADER_SIGNATURE) {
+            return Err(format!("corrupt zip entry {}", entry.name));
+        }
+        // The local header's own name and extra field lengths can differ from the directory's
+        let name_length = Zip::u16_at(data, header + 26).unwrap_or(0) as usize;
+        let extra_length = Zip::u16_at(data, header + 28).unwrap_or(0) as usize;
+        let start = header + 30 + name_length + extra_length;
+        let compressed = data
+            .get(start..start + entry.compressed_size)
+            .ok_or(format!("zip entry {} is cut off", entry.name))?;
+
+        match entry.compression {
+            0 => Ok(compressed.to_vec()),
+            8 => Inflate::decompress(compressed, limit)
+                .map_err(|e| format!("{} in {}", e, entry.name)),
+            method => Err(format!(
+                "{} uses compression method {}, which isn't supported",
+                entry.name, method
+            )),
+        }
+    }
+
+    fn u16_at(data: &[u8], offset: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            data.get(offset..offset + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32_at(data: &[u8], offset: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            data.get(offset..offset + 4)?.try_into().ok()?,
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Made by Info-ZIP's zip, with a directory, a stored rom, a deflated description and a
+    // comment. Its local headers have longer extra fields than the central directory.
+    const ARCHIVE: [u8; 490] = [
+        0x50, 0x4B, 0x03, 0x04, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x1C, 0x00,
+        0x72, 0x6F, 0x6D, 0x73, 0x2F, 0x55, 0x54, 0x09, 0x00, 0x03, 0x00, 0xE1, 0x0B, 0x5E, 0x00,
+        0xE1, 0x0B, 0x5E, 0x75, 0x78, 0x0B, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00,
+        0x00, 0x00, 0x00, 0x50, 0x4B, 0x03, 0x04, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x21, 0x50, 0x6F, 0x7F, 0x52, 0xF0, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0D,
+        0x00, 0x1C, 0x00, 0x72, 0x6F, 0x6D, 0x73, 0x2F, 0x50, 0x6F, 0x6E, 0x67, 0x2E, 0x63, 0x68,
+        0x38, 0x55, 0x54, 0x09, 0x00, 0x03, 0x00, 0xE1, 0x0B, 0x5E, 0x00, 0xE1, 0x0B, 0x5E, 0x75,
+        0x78, 0x0B, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0xE0, 0x12, 0x00, 0x50, 0x4B, 0x03, 0x04, 0x14, 0x00, 0x00, 0x00, 0x08, 0x00, 0x00, 0x00,
+        0x21, 0x50, 0xCB, 0xA3, 0xB4, 0x50, 0x0C, 0x00, 0x00, 0x00, 0x1D, 0x00, 0x00, 0x00, 0x0D,
+        0x00, 0x1C, 0x00, 0x72, 0x6F, 0x6D, 0x73, 0x2F, 0x50, 0x6F, 0x6E, 0x67, 0x2E, 0x74, 0x78,
+        0x74, 0x55, 0x54, 0x09, 0x00, 0x03, 0x00, 0xE1, 0x0B, 0x5E, 0x00, 0xE1, 0x0B, 0x5E, 0x75,
+        0x78, 0x0B, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x0B,
+        0xC8, 0xCF, 0x4B, 0xD7, 0x51, 0x08, 0xC0, 0x4A, 0x2A, 0x02, 0x00, 0x50, 0x4B, 0x01, 0x02,
+        0x1E, 0x03, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x05, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x10, 0x00, 0xED, 0x41, 0x00, 0x00, 0x00, 0x00, 0x72, 0x6F, 0x6D,
+        0x73, 0x2F, 0x55, 0x54, 0x05, 0x00, 0x03, 0x00, 0xE1, 0x0B, 0x5E, 0x75, 0x78, 0x0B, 0x00,
+        0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x50, 0x4B, 0x01, 0x02,
+        0x1E, 0x03, 0x0A, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x21, 0x50, 0x6F, 0x7F, 0x52,
+        0xF0, 0x04, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x0D, 0x00, 0x18, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xA4, 0x81, 0x3F, 0x00, 0x00, 0x00, 0x72, 0x6F, 0x6D,
+        0x73, 0x2F, 0x50, 0x6F, 0x6E, 0x67, 0x2E, 0x63, 0x68, 0x38, 0x55, 0x54, 0x05, 0x00, 0x03,
+        0x00, 0xE1, 0x0B, 0x5E, 0x75, 0x78, 0x0B, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04,
+        0x00, 0x00, 0x00, 0x00, 0x50, 0x4B, 0x01, 0x02, 0x1E, 0x03, 0x14, 0x00, 0x00, 0x00, 0x08,
+        0x00, 0x00, 0x00, 0x21, 0x50, 0xCB, 0xA3, 0xB4, 0x50, 0x0C, 0x00, 0x00, 0x00, 0x1D, 0x00,
+        0x00, 0x00, 0x0D, 0x00, 0x18, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0xA4,
+        0x81, 0x8A, 0x00, 0x00, 0x00, 0x72, 0x6F, 0x6D, 0x73, 0x2F, 0x50, 0x6F, 0x6E, 0x67, 0x2E,
+        0x74, 0x78, 0x74, 0x55, 0x54, 0x05, 0x00, 0x03, 0x00, 0xE1, 0x0B, 0x5E, 0x75, 0x78, 0x0B,
+        0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x50, 0x4B, 0x05,
+        0x06, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x03, 0x00, 0xF1, 0x00, 0x00, 0x00, 0xDD, 0x00,
+        0x00, 0x00, 0x06, 0x00, 0x43, 0x48, 0x49, 0x50, 0x2D, 0x38,
+    ];
+
+    #[test]
+    fn lists_files_but_not_directories() {
+        assert!(Zip::is_zip(&ARCHIVE));
+        let names: Vec<String> = Zip::entries(&ARCHIVE)
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert_eq!(names, ["roms/Pong.ch8", "roms/Pong.txt"]);
+    }
+
+    #[test]
+    fn extracts_stored_and_deflated_entries() {
+        let entries = Zip::entries(&ARCHIVE).unwrap();
+        assert_eq!(
+            Zip::extract(&ARCHIVE, &entries[0], 0x100).unwrap(),
+            [0x00, 0xE0, 0x12, 0x00]
+        );
+        assert_eq!(
+            Zip::extract(&ARCHIVE, &entries[1], 0x100).unwrap(),
+            b"Pong, Pong, Pong, Pong, Pong!"
+        );
+        assert!(Zip::extract(&ARCHIVE, &entries[1], 10).is_err());
+    }
+
+    #[test]
+    fn truncated_archives() {
+        assert!(Zip::entries(&ARCHIVE[..100]).is_err());
+        assert!(!Zip::is_zip(b"PK"));
+    }
+}
@@ -0,0 +1,500 @@
+use crate::chip8::{
+    keymap::DirectionalKeys,
+    memory_map::MemoryMap,
+    platform::{Platform, Quirks},
+    processor::Processor,
+    screen::{Palette, ScaleMode},
+    timing::Timing,
+    vip::VIP_INTERPRETER_SIZE,
+};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+// Window starts at 1024x512 unless overridden with --scale or the config
+pub const DEFAULT_SCALE: u32 = 16;
+// Where the launcher looks for roms unless overridden with --rom-dir
+pub const DEFAULT_ROM_DIR: &str = "roms";
+// How long trace and test run for unless told otherwise
+const DEFAULT_TRACE_STEPS: usize = 1000;
+const DEFAULT_TEST_FRAMES: usize = 600;
+
+pub const USAGE: &str = "\
+Usage: chip8-interpreter [run] [options] [rom]
+       chip8-interpreter disasm [--patch FILE] <rom>
+       chip8-interpreter trace [--steps N] [emulation options] <rom>
+       chip8-interpreter test [--frames N] [emulation options] <rom>
+       chip8-interpreter info [--patch FILE] <rom>
+       chip8-interpreter import [--roms DIR] <programs.json>
+
+Commands:
+  run       Play a rom in a window, or pick one from the launcher (the default)
+  disasm    Print the rom as assembly
+  trace     Run the rom without a window, printing every instruction and the registers
+  test      Run the rom without a window for a while, then print the screen
+  info      Print the rom's size, hash and what's known about it
+  import    Add the games in a chip8Archive programs.json to the rom database in roms.toml
+
+Run options:
+  --scale N          Starting window size as a multiple of 64x32 (default 16)
+  --integer-scale    Only scale by whole numbers, for perfectly even pixels
+  --palette OFF,ON   Pixel colours, like \"#000000,#ffffff\"
+  --mute             Don't play any sound
+  --keymap FILE      Apply the key bindings in FILE on top of any others
+  --directional      Bind the arrow keys and space to the rom's direction keys
+  --wasd             Like --directional, but with W, A, S and D
+  --rom-dir DIR      Directory of roms listed by the launcher (default roms)
+  --doc              Print the rom's documentation instead of running it
+
+Rom options, for run, disasm, trace, test and info:
+  --patch FILE       Apply an IPS or BPS patch to the rom. Without this, a patch named like
+                     the rom (game.ips or game.bps next to game.ch8) is applied.
+
+Emulation options, for run, trace and test:
+  --ipf N            Instructions per 60Hz frame, the emulation speed
+  --ips N            Instructions per second, rounded to whole instructions per frame
+  --vip-timing       Give each instruction the time it took on the COSMAC VIP, which suits
+                     the oldest games, instead of running a fixed number per frame
+  --vip-memory       Keep the display at 0xF00 and the stack at 0xEA0 in memory like the VIP,
+                     for roms that read or change them directly
+  --vip-system FILE  Emulate the whole COSMAC VIP, running the CHIP-8 interpreter in FILE
+                     (a VIP binary, loaded at 0x000) on its 1802 instead of the built-in one
+  --platform NAME    Run the rom as written for chip8, schip, xochip, hires (the two-page
+                     64x64 variant) or chip8x, instead of what it's recognized as
+  --quirks LIST      Platform and quirks to use, like \"schip\" or \"chip8,!vf_reset,wrap_sprites\".
+                     Platforms are chip8, schip, xochip, hires and chip8x, which only set the
+                     quirks here, use --platform for the rest. Quirks are vf_reset,
+                     memory_increment, shift_vx, jump_vx, wrap_sprites and key_press.
+  --stack-depth N    How many calls can be nested (12 on the VIP, 16 on SCHIP, the default)
+  --seed N           Seed the random number generator, for repeatable runs
+
+trace and test options:
+  --steps N          Instructions to trace (default 1000)
+  --frames N         Frames to run before printing the screen, at 60 a second (default 600)
+
+import options:
+  --roms DIR         Where the archive's roms are (default the roms directory next to
+                     programs.json)";
+
+// Settings that change how a rom runs, which override anything from the rom database
+#[derive(Clone, Debug, Default)]
+pub struct EmulationOptions {
+    pub platform: Option<Platform>, // Given to the loader, as the rom is loaded for it
+    pub instructions_per_frame: Option<u32>,
+    pub timing: Option<Timing>,
+    pub memory_map: Option<MemoryMap>, // Set before loading, as it decides the room for the rom
+    pub stack_depth: Option<usize>,
+    pub quirks: Option<Quirks>,
+    pub seed: Option<u64>,
+    pub vip_interpreter: Option<Vec<u8>>,
+}
+
+impl EmulationOptions {
+    pub fn apply(&self, processor: &mut Processor) {
+        if let Some(instructions_per_frame) = self.instructions_per_frame {
+            processor.instructions_per_frame = instructions_per_frame;
+        }
+        if let Some(timing) = self.timing {
+            processor.timing = timing;
+        }
+        if let Some(stack_depth) = self.stack_depth {
+            processor.set_stack_depth(stack_depth);
+        }
+        if let Some(quirks) = self.quirks {
+            processor.quirks = quirks;
+        }
+        if let Some(seed) = self.seed {
+            processor.rng = StdRng::seed_from_u64(seed);
+        }
+        if let Some(interpreter) = &self.vip_interpreter {
+            processor.start_vip(interpreter);
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct RunOptions {
+    pub rom_path: Option<String>,
+    pub patch_path: Option<String>, // Only for the rom given, not ones picked in the launcher
+    pub rom_dir: Option<PathBuf>,
+    pub scale: Option<u32>,
+    pub scale_mode: ScaleMode,
+    pub palette: Option<Palette>,
+    pub mute: bool,
+    pub keymap_path: Option<String>,
+    pub directional_keys: Option<DirectionalKeys>,
+    pub print_doc: bool,
+    pub emulation: EmulationOptions,
+}
+
+impl Default for RunOptions {
+    fn default() -> Self {
+        Self {
+            rom_path: None,
+            patch_path: None,
+            rom_dir: None,
+            scale: None,
+            scale_mode: ScaleMode::Fit,
+            palette: None,
+            mute: false,
+            keymap_path: None,
+            directional_keys: None,
+            print_doc: false,
+            emulation: EmulationOptions::default(),
+        }
+    }
+}
+
+pub enum Command {
+    Run(RunOptions),
+    Disasm {
+        rom_path: String,
+        patch_path: Option<String>,
+    },
+    Trace {
+        rom_path: String,
+        patch_path: Option<String>,
+        steps: usize,
+        emulation: EmulationOptions,
+    },
+    Test {
+        rom_path: String,
+        patch_path: Option<String>,
+        frames: usize,
+        emulation: EmulationOptions,
+    },
+    Info {
+        rom_path: String,
+        patch_path: Option<String>,
+    },
+    Import {
+        programs_path: String,
+        roms_dir: Option<PathBuf>,
+    },
+    Help,
+}
+
+pub struct Cli {}
+
+impl Cli {
+    // Anything that doesn't start with a command is treated as options for run, so
+    // "chip8-interpreter game.ch8" keeps working. A first word that's neither a rom nor an
+    // option is taken to be a mistyped command.
+    pub fn parse(args: Vec<String>) -> Result<Command, String> {
+        let mut args = args.into_iter().peekable();
+        let command = match args.peek().map(String::as_str) {
+            Some("run" | "disasm" | "trace" | "test" | "info" | "import" | "help") => {
+                args.next().unwrap()
+            }
+            Some(word)
+                if !word.starts_with('-')
+                    && !word.contains(['.', '/', '\\'])
+                    && !Path::new(word).exists() =>
+            {
+                return Err(format!(
+                    "Unknown command {}, run with --help to see the commands",
+                    word
+                ))
+            }
+            _ => "run".to_string(),
+        };
+
+        let mut run = RunOptions::default();
+        let mut emulation = EmulationOptions::default();
+        let mut steps = DEFAULT_TRACE_STEPS;
+        let mut frames = DEFAULT_TEST_FRAMES;
+        let mut rom_path = None;
+        let mut roms_dir = None;
+        let mut patch_path = None;
+        let mut speed_flag: Option<String> = None;
+
+        while let Some(arg) = args.next() {
+            let is_run = command == "run";
+            let emulates = matches!(command.as_str(), "run" | "trace" | "test");
+            if matches!(arg.as_str(), "--ipf" | "--ips") && emulates {
+                if let Some(flag) = speed_flag.filter(|flag| *flag != arg) {
+                    return Err(format!(
+                        "{} and {} both set the speed, only one can be given",
+                        flag, arg
+                    ));
+                }
+                speed_flag = Some(arg.clone());
+            }
+            match arg.as_str() {
+                "-h" | "--help" => return Ok(Command::Help),
+                "--ipf" if emulates => {
+                    emulation.instructions_per_frame = Some(Cli::positive(&mut args, &arg)?)
+                }
+                "--ips" if emulates => {
+                    let ips = Cli::positive(&mut args, &arg)?;
+                    emulation.instructions_per_frame = Some(((ips + 30) / 60).max(1));
+                }
+                "--vip-timing" if emulates => emulation.timing = Some(Timing::Vip),
+                "--vip-memory" if emulates => emulation.memory_map = Some(MemoryMap::Vip),
+                "--vip-system" if emulates => {
+                    let path: String = Cli::value(&mut args, &arg, "a path")?;
+                    emulation.vip_interpreter = Some(Cli::read_interpreter(&path)?);
+                }
+                "--stack-depth" if emulates => {
+                    emulation.stack_depth = Some(Cli::positive(&mut args, &arg)? as usize)
+                }
+                "--seed" if emulates => {
+                    emulation.seed = Some(Cli::value(&mut args, &arg, "a whole number")?)
+                }
+                "--platform" if emulates => {
+                    let name: String = Cli::value(&mut args, &arg, "a platform")?;
+                    emulation.platform = Some(Platform::parse(&name).ok_or(format!(
+                        "Unknown platform \"{}\" for --platform, it can be chip8, schip, xochip, \
+                         hires or chip8x",
+                        name
+                    ))?);
+                }
+                "--quirks" if emulates => {
+                    let list: String = Cli::value(&mut args, &arg, "a list of quirks")?;
+                    emulation.quirks =
+                        Some(Quirks::parse_list(&list).map_err(|e| format!("{} in --quirks", e))?);
+                }
+                "--steps" if command == "trace" => steps = Cli::positive(&mut args, &arg)? as usize,
+                "--frames" if command == "test" => {
+                    frames = Cli::positive(&mut args, &arg)? as usize
+                }
+                "--scale" if is_run => run.scale = Some(Cli::positive(&mut args, &arg)?),
+                "--integer-scale" if is_run => run.scale_mode = ScaleMode::Integer,
+                "--palette" if is_run => {
+                    let text: String = Cli::value(&mut args, &arg, "two colours")?;
+                    run.palette = Some(Cli::parse_palette(&text)?);
+                }
+                "--mute" if is_run => run.mute = true,
+                "--keymap" if is_run => {
+                    run.keymap_path = Some(Cli::value(&mut args, &arg, "a path")?)
+                }
+                "--directional" if is_run => run.directional_keys = Some(DirectionalKeys::Arrows),
+                "--wasd" if is_run => run.directional_keys = Some(DirectionalKeys::Wasd),
+                "--rom-dir" if is_run => {
+                    run.rom_dir = Some(PathBuf::from(Cli::value::<String>(
+                        &mut args, &arg, "a path",
+                    )?))
+                }
+                "--doc" if is_run => run.print_doc = true,
+                "--patch" if command != "import" => {
+                    patch_path = Some(Cli::value(&mut args, &arg, "a path")?)
+                }
+                "--roms" if command == "import" => {
+                    roms_dir = Some(PathBuf::from(Cli::value::<String>(
+                        &mut args, &arg, "a path",
+                    )?))
+                }
+                _ if arg.starts_with('-') && arg != "-" => {
+                    return Err(format!(
+                        "Unknown option {} for {}, run with --help to see the options",
+                        arg, command
+                    ))
+                }
+                _ if rom_path.is_some() => {
+                    return Err(format!(
+                        "Unexpected argument {}, only one rom can be given",
+                        arg
+                    ))
+                }
+                _ => {
+                    Cli::check_rom(&arg)?;
+                    rom_path = Some(arg);
+                }
+            }
+        }
+
+        if command == "run" {
+            run.rom_path = rom_path;
+            run.patch_path = patch_path;
+            run.emulation = emulation;
+            return Ok(Command::Run(run));
+        }
+        if command == "help" {
+            return Ok(Command::Help);
+        }
+
+        if command == "import" {
+            let programs_path = rom_path.ok_or("import needs a programs.json")?;
+            return Ok(Command::Import {
+                programs_path,
+                roms_dir,
+            });
+        }
+
+        let rom_path = rom_path.ok_or(format!(
+            "{} needs a rom, like: {} game.ch8",
+            command, command
+        ))?;
+        Ok(match command.as_str() {
+            "disasm" => Command::Disasm {
+                rom_path,
+                patch_path,
+            },
+            "trace" => Command::Trace {
+                rom_path,
+                patch_path,
+                steps,
+                emulation,
+            },
+            "test" => Command::Test {
+                rom_path,
+                patch_path,
+                frames,
+                emulation,
+            },
+            _ => Command::Info {
+                rom_path,
+                patch_path,
+            },
+        })
+    }
+
+    // Catches missing files up front, so they don't end in a panic from the loader. "-" reads
+    // the rom from standard input.
+    pub fn check_rom(path: &str) -> Result<(), String> {
+        let path = Path::new(path);
+        if path == Path::new("-") {
+            Ok(())
+        } else if path.is_dir() {
+            Err(format!("{} is a directory, not a rom", path.display()))
+        } else if !path.exists() {
+            Err(format!("Couldn't find a rom at {}", path.display()))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn read_interpreter(path: &str) -> Result<Vec<u8>, String> {
+        let interpreter =
+            fs::read(path).map_err(|e| format!("Failed to read interpreter {}: {}", path, e))?;
+        if interpreter.is_empty() || interpreter.len() > VIP_INTERPRETER_SIZE {
+            return Err(format!(
+                "The interpreter {} is {} bytes, it has to fit in the {} before the rom",
+                path,
+                interpreter.len(),
+                VIP_INTERPRETER_SIZE
+            ));
+        }
+        Ok(interpreter)
+    }
+
+    fn parse_palette(text: &str) -> Result<Palette, String> {
+        let colors: Option<Vec<[u8; 3]>> = text.split(',').map(Palette::parse_color).collect();
+        match colors.as_deref() {
+            Some([off, on]) => Ok(Palette { off: *off, on: *on }),
+            _ => Err(format!(
+                "--palette must be two colours like \"#000000,#ffffff\", not \"{}\"",
+                text
+            )),
+        }
+    }
+
+    fn value<T: FromStr>(
+        args: &mut impl Iterator<Item = String>,
+        flag: &str,
+        expected: &str,
+    ) -> Result<T, String> {
+        args.next()
+            .and_then(|value| value.parse().ok())
+            .ok_or(format!("{} must be followed by {}", flag, expected))
+    }
+
+    fn positive(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<u32, String> {
+        match Cli::value(args, flag, "a whole number above 0") {
+            Ok(0) => Err(format!(
+                "{} must be followed by a whole number above 0",
+                flag
+            )),
+            result => result,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "-" is standard input, so these don't need a rom on disk
+    fn parse(args: &str) -> Result<Command, String> {
+        Cli::parse(args.split_whitespace().map(String::from).collect())
+    }
+
+    #[test]
+    fn commands_and_their_options() {
+        let Ok(Command::Run(run)) = parse("- --ipf 20 --mute --wasd") else {
+            panic!("expected run");
+        };
+        assert_eq!(run.rom_path.as_deref(), Some("-"));
+        assert_eq!(run.emulation.instructions_per_frame, Some(20));
+        assert!(run.mute);
+        assert_eq!(run.directional_keys, Some(DirectionalKeys::Wasd));
+
+        let Ok(Command::Trace {
+            steps, emulation, ..
+        }) = parse("trace - --steps 5 --ips 600")
+        else {
+            panic!("expected trace");
+        };
+        assert_eq!(steps, 5);
+        assert_eq!(emulation.instructions_per_frame, Some(10));
+
+        assert!(matches!(
+            parse("test - --frames 3"),
+            Ok(Command::Test { frames: 3, .. })
+        ));
+        assert!(matches!(parse("info -"), Ok(Command::Info { .. })));
+        assert!(matches!(parse("run --help"), Ok(Command::Help)));
+    }
+
+    #[test]
+    fn option_errors() {
+        let error = |args: &str| parse(args).err().unwrap();
+
+        assert_eq!(
+            error("- --ipf"),
+            "--ipf must be followed by a whole number above 0"
+        );
+        assert_eq!(
+            error("- --ipf 0"),
+            "--ipf must be followed by a whole number above 0"
+        );
+        assert_eq!(
+            error("- --seed x"),
+            "--seed must be followed by a whole number"
+        );
+        assert!(error("- --platform gameboy").starts_with("Unknown platform \"gameboy\""));
+        assert!(error("- --palette red").starts_with("--palette must be two colours"));
+        assert_eq!(
+            error("info - --mute"),
+            "Unknown option --mute for info, run with --help to see the options"
+        );
+        assert_eq!(
+            error("- -"),
+            "Unexpected argument -, only one rom can be given"
+        );
+        assert_eq!(error("trace"), "trace needs a rom, like: trace game.ch8");
+    }
+
+    #[test]
+    fn ipf_and_ips_conflict() {
+        assert_eq!(
+            parse("- --ipf 10 --ips 600").err().unwrap(),
+            "--ipf and --ips both set the speed, only one can be given"
+        );
+        assert!(parse("- --ips 600 --ips 660").is_ok());
+    }
+
+    #[test]
+    fn unknown_commands() {
+        assert_eq!(
+            parse("tarce -").err().unwrap(),
+            "Unknown command tarce, run with --help to see the commands"
+        );
+        assert_eq!(
+            parse("missing.ch8").err().unwrap(),
+            "Couldn't find a rom at missing.ch8"
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::chip8::{
+    constants::REAL_WIDTH,
+    database::{RomDatabase, RomProfile},
+    disassembler::Disassembler,
+    execution::PixelState,
+    loader::{Loader, RomInfo},
+    platform::Platform,
+    processor::{Halt, Processor, ProcessorError},
+    sha1::Sha1,
+};
+use crate::cli::EmulationOptions;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+// The commands that work without a window, for debugging roms and the interpreter itself
+pub struct Commands {}
+
+impl Commands {
+    pub fn disasm(rom_path: &str, patch_path: Option<&str>) -> Result<(), String> {
+        let (processor, rom) = Commands::load(rom_path, patch_path, &EmulationOptions::default())?;
+
+        for line in Disassembler::listing(&processor.memory, rom.start, rom.end) {
+            println!("{}", line);
+        }
+        Ok(())
+    }
+
+    pub fn trace(
+        rom_path: &str,
+        patch_path: Option<&str>,
+        steps: usize,
+        emulation: &EmulationOptions,
+    ) -> Result<(), String> {
+        let (mut processor, _) = Commands::load(rom_path, patch_path, emulation)?;
+        // The interpreter on an emulated VIP keeps its state wherever it likes
+        if processor.vip.is_some() {
+            return Err(
+                "trace can't follow roms run with --vip-system, use test instead".to_string(),
+            );
+        }
+
+        for _ in 0..steps {
+            match processor.halt {
+                // Nothing is pressed without a window, so this would wait forever
+                Some(Halt::WaitingForPress { .. } | Halt::WaitingForRelease { .. }) => {
+                    println!(
+                        "{:03X}  Waiting for a key press, stopping",
+                        processor.PC - 2
+                    );
+                    break;
+                }
+                Some(Halt::Error(error)) => return Err(error.to_string()),
+                None => {}
+            }
+
+            let Some(opcode) = processor.opcode_at(processor.PC) else {
+                let error = ProcessorError::EndOfMemory { pc: processor.PC };
+                return Err(error.to_string());
+            };
+            let registers: Vec<String> = processor
+                .V_REGS
+                .iter()
+                .map(|value| format!("{:02X}", value))
+                .collect();
+            let stack: Vec<String> = processor
+                .call_stack()
+                .iter()
+                .map(|address| format!("{:03X}", address))
+                .collect();
+            println!(
+                "{:03X}  {:04X}  {:<20} V={} I={:03X} DT={:02X} ST={:02X} S=[{}]",
+                processor.PC,
+                opcode,
+                Disassembler::disassemble(opcode),
+                registers.join(" "),
+                processor.I,
+                processor.delay_timer,
+                processor.sound_timer,
+                stack.join(" ")
+            );
+
+            processor.step();
+        }
+        Ok(())
+    }
+
+    // Runs the rom for a number of frames and prints what ends up on screen, which is all
+    // test roms need to report their results
+    pub fn test(
+        rom_path: &str,
+        patch_path: Option<&str>,
+        frames: usize,
+        emulation: &EmulationOptions,
+    ) -> Result<(), String> {
+        let (mut processor, _) = Commands::load(rom_path, patch_path, emulation)?;
+
+        let mut error = None;
+        for _ in 0..frames {
+            processor.run_frame();
+            if let Some(Halt::Error(halt_error)) = processor.halt {
+                error = Some(halt_error);
+                break;
+            }
+        }
+
+        for row in processor.display.chunks_exact(REAL_WIDTH) {
+            let line: String = row
+                .iter()
+                .map(|pixel| match pixel {
+                    PixelState::On => '#',
+                    PixelState::Off => '.',
+                })
+                .collect();
+            println!("{}", line);
+        }
+        match error {
+            Some(error) => Err(error.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    pub fn info(rom_path: &str, patch_path: Option<&str>) -> Result<(), String> {
+        let (processor, rom) = Commands::load(rom_path, patch_path, &EmulationOptions::default())?;
+
+        println!("File:      {}", rom_path);
+        println!(
+            "Size:      {} bytes, loaded at {:03X}-{:03X}",
+            rom.size,
+            rom.start,
+            rom.end - 1
+        );
+        if let Some(patch) = &rom.patch {
+            println!("Patch:     {}", patch.display());
+        }
+        println!("SHA-1:     {}", rom.sha1);
+
+        match &rom.profile {
+            Some(profile) => {
+                println!(
+                    "Title:     {}",
+                    profile.display_name().unwrap_or("(untitled)".to_string())
+                );
+                let source = if rom.cartridge && profile.title.is_none() {
+                    "the cartridge"
+                } else {
+                    "the rom database"
+                };
+                match profile.platform {
+                    Some(platform) => println!("Platform:  {} (from {})", platform.name(), source),
+                    None => Commands::print_detected_platform(rom.platform),
+                }
+                if let Some(speed) = profile.speed {
+                    println!("Speed:     {} instructions per frame", speed);
+                }
+            }
+            None => {
+                println!("Title:     not in the rom database");
+                Commands::print_detected_platform(rom.platform);
+            }
+        }
+
+        let quirks = processor.quirks.enabled();
+        println!(
+            "Quirks:    {}",
+            if quirks.is_empty() {
+                "none".to_string()
+            } else {
+                quirks.join(", ")
+            }
+        );
+        match Loader::find_doc(Path::new(rom_path)) {
+            Some(doc) => println!("Docs:      {}", doc.display()),
+            None => println!("Docs:      none"),
+        }
+        Ok(())
+    }
+
+    // The chip8Archive keeps each game's rom as roms/<name>.ch8, next to programs.json, which
+    // describes them by name. They're matched up by hashing the roms.
+    pub fn import(programs_path: &str, roms_dir: Option<&Path>) -> Result<(), String> {
+        let text = fs::read_to_string(programs_path)
+            .map_err(|e| format!("Failed to read {}: {}", programs_path, e))?;
+        let programs: serde_json::Value = serde_json::from_str(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", programs_path, e))?;
+        let programs = programs
+            .as_object()
+            .ok_or(format!("{} isn't a list of programs", programs_path))?;
+        let roms_dir = match roms_dir {
+            Some(dir) => dir.to_path_buf(),
+            None => Path::new(programs_path).with_file_name("roms"),
+        };
+
+        let mut profiles = Vec::new();
+        let mut missing = Vec::new();
+        for (name, entry) in programs {
+            let Some(entry) = entry.as_object() else {
+                continue;
+            };
+            match fs::read(roms_dir.join(format!("{}.ch8", name))) {
+                Ok(rom) => profiles.push((
+                    Sha1::hex_digest(&rom),
+                    RomProfile::from_archive_entry(entry),
+                )),
+                Err(_) => missing.push(name.as_str()),
+            }
+        }
+        if profiles.is_empty() {
+            return Err(format!(
+                "None of the roms were found in {}",
+                roms_dir.display()
+            ));
+        }
+
+        let path = RomDatabase::save(&profiles)?;
+        println!("Imported {} roms into {}", profiles.len(), path.display());
+        if !missing.is_empty() {
+            println!(
+                "Skipped {} without a rom in {}: {}",
+                missing.len(),
+                roms_dir.display(),
+                missing.join(", ")
+            );
+        }
+        Ok(())
+    }
+
+    fn print_detected_platform(platform: Platform) {
+        println!(
+            "Platform:  {} (guessed from its instructions)",
+            platform.name()
+        );
+    }
+
+    // A processor with nothing attached, no screen and no keys held
+    fn load(
+        rom_path: &str,
+        patch_path: Option<&str>,
+        emulation: &EmulationOptions,
+    ) -> Result<(Processor, RomInfo), String> {
+        let mut processor = Processor::new(Arc::new(Mutex::new([false; 16])));
+        if let Some(memory_map) = emulation.memory_map {
+            processor.memory_map = memory_map;
+        }
+        let rom = Loader::load_rom(
+            &mut processor,
+            rom_path,
+            patch_path.map(Path::new),
+            emulation.platform,
+            &RomDatabase::load(),
+        )
+        .map_err(|e| e.to_string())?;
+        emulation.apply(&mut processor);
+        Ok((processor, rom))
+    }
+}
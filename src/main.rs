@@ -1,138 +1,692 @@
 mod chip8;
-use chip8::{loader::Loader, processor::Processor, square::SquareWave};
-use pixels::{wgpu::Color, Pixels, SurfaceTexture};
-use rodio::{Sink, Source};
+mod cli;
+mod commands;
+use chip8::{
+    analysis::Analyzer,
+    beeper::{Beeper, DEFAULT_BEEP_FREQUENCY, DEFAULT_VOLUME},
+    config::Config,
+    constants::{DEFAULT_INSTRUCTIONS_PER_FRAME, REAL_HEIGHT, REAL_WIDTH},
+    database::RomDatabase,
+    doc_viewer::DocViewer,
+    keymap::Keymap,
+    launcher::Launcher,
+    loader::Loader,
+    overlay::{DIM_COLOR, TEXT_COLOR},
+    processor::{Halt, Processor},
+    scheduler::Scheduler,
+    screen::{Palette, Screen},
+    speed::{Speed, SpeedMode},
+    timing::Timing,
+    toml::TomlValue,
+};
+use cli::{Cli, Command, RunOptions, DEFAULT_ROM_DIR, DEFAULT_SCALE, USAGE};
+use commands::Commands;
+use pixels::{Pixels, SurfaceTexture};
 use std::{
     env,
-    sync::{Arc, Mutex},
+    path::Path,
+    sync::{mpsc, Arc, Mutex},
     thread,
     time::{Duration, Instant},
 };
 use winit::{
     dpi::LogicalSize,
     event::{Event, WindowEvent},
-    event_loop::EventLoop,
+    event_loop::{ControlFlow, EventLoop},
     keyboard::KeyCode,
-    window::WindowBuilder,
+    window::{Fullscreen, Window, WindowBuilder},
 };
 use winit_input_helper::WinitInputHelper;
 
-const WINDOW_WIDTH: u32 = 1024;
-const WINDOW_HEIGHT: u32 = 512;
-const REAL_WIDTH: usize = 64;
-const REAL_HEIGHT: usize = 32;
-
-// Original COSMAC VIP keypad looks like:
-// 1   2   3   C
-// 4   5   6   D
-// 7   8   9   E
-// A   0   B   F
-// This mapping is made such that given a key value 0-F,
-// you can easily get the corresponding keyboard keycode
-const KEY_BINDINGS: [KeyCode; 16] = [
-    KeyCode::KeyX,
-    KeyCode::Digit1,
-    KeyCode::Digit2,
-    KeyCode::Digit3,
-    KeyCode::KeyQ,
-    KeyCode::KeyW,
-    KeyCode::KeyE,
-    KeyCode::KeyA,
-    KeyCode::KeyS,
-    KeyCode::KeyD,
-    KeyCode::KeyZ,
-    KeyCode::KeyC,
-    KeyCode::Digit4,
-    KeyCode::KeyR,
-    KeyCode::KeyF,
-    KeyCode::KeyV,
-];
+const WINDOW_TITLE: &str = "Chip8 Interpreter";
+const SPEED_INDICATOR_DURATION: Duration = Duration::from_secs(2);
+
+// Everything that belongs to the rom being played, replaced when switching games
+struct Game {
+    keymap: Keymap,
+    second_keys: Arc<Mutex<[bool; 16]>>, // CHIP-8X's second keypad, shared with the processor
+    key_help: Vec<(String, [u8; 4])>,
+    title: String,
+    palette: Palette,
+    doc: Option<DocViewer>,
+    sha1: String,
+    volume: f32,
+    beep_frequency: f32,
+    volume_in_rom_config: bool, // Volume changes are saved to the rom's section if it has one
+}
+
+fn load_game(
+    rom_path: &str,
+    options: &RunOptions,
+    config: &Config,
+    database: &RomDatabase,
+    pressed_keys: &Arc<Mutex<[bool; 16]>>,
+) -> Result<(Processor, Game), String> {
+    // Speed comes from, weakest first: the global config, the rom database, the rom's config
+    // section and the command line
+    let mut processor = Processor::new(Arc::clone(pressed_keys));
+    if let Some(speed) = config.settings.speed {
+        processor.instructions_per_frame = speed;
+    }
+    if let Some(memory_map) = options.emulation.memory_map {
+        processor.memory_map = memory_map;
+    }
+    let patch_path = options
+        .patch_path
+        .as_ref()
+        .filter(|_| options.rom_path.as_deref() == Some(rom_path))
+        .map(Path::new);
+    let rom = Loader::load_rom(
+        &mut processor,
+        rom_path,
+        patch_path,
+        options.emulation.platform,
+        database,
+    )
+    .map_err(|e| e.to_string())?;
+    if let Some(patch) = &rom.patch {
+        println!("Patched with {}", patch.display());
+    }
+    let settings = config.rom(&rom.sha1);
+    if let Some(speed) = settings.speed {
+        processor.instructions_per_frame = speed;
+    }
+    options.emulation.apply(&mut processor);
+    let profile = rom.profile.unwrap_or_default();
+    if let (Some(name), Some(platform)) = (profile.display_name(), profile.platform) {
+        println!(
+            "Recognized {}, running with {} quirks",
+            name,
+            platform.name()
+        );
+    }
+
+    let config_keymaps: Vec<_> = [&config.settings.keymap, &settings.keymap]
+        .into_iter()
+        .flatten()
+        .collect();
+    let mut keymap = Keymap::load(rom_path, &config_keymaps);
+    if let Some(path) = &options.keymap_path {
+        keymap
+            .apply_file(Path::new(path))
+            .map_err(|e| format!("Failed to load keymap {}: {}", path, e))?;
+    }
+
+    let mut key_usage = Analyzer::key_usage(&processor.memory, rom.start);
+    // Key hints from the rom database are known to be right, so they beat the guesswork
+    for key in profile.keys.keys() {
+        key_usage.keys[key] = true;
+    }
+    if let Some(directional_keys) = options.directional_keys {
+        let directions = if profile.keys.is_empty() {
+            Analyzer::directions(&key_usage)
+        } else {
+            profile.keys
+        };
+        if directions.is_empty() {
+            println!("Couldn't detect this rom's direction keys, keeping the normal bindings");
+        } else {
+            keymap.bind_directions(&directions, directional_keys);
+        }
+    }
+    let key_help = keymap.help_lines(&key_usage);
+
+    let title = match profile.display_name() {
+        Some(name) => format!("{} - {}", WINDOW_TITLE, name),
+        None => WINDOW_TITLE.to_string(),
+    };
+
+    let game = Game {
+        keymap,
+        second_keys: Arc::clone(&processor.second_keys),
+        key_help,
+        title,
+        palette: options
+            .palette
+            .or(settings.palette)
+            .or(profile.palette)
+            .or(config.settings.palette)
+            .unwrap_or_default(),
+        doc: Loader::load_doc(Path::new(rom_path))
+            .map(|text| DocViewer::new(&Loader::doc_title(Path::new(rom_path)), &text)),
+        volume: settings
+            .volume
+            .or(config.settings.volume)
+            .unwrap_or(DEFAULT_VOLUME),
+        beep_frequency: settings
+            .beep_frequency
+            .or(config.settings.beep_frequency)
+            .unwrap_or(DEFAULT_BEEP_FREQUENCY),
+        volume_in_rom_config: settings.volume.is_some(),
+        sha1: rom.sha1,
+    };
+    Ok((processor, game))
+}
+
+// Games are started by handing a freshly loaded processor to the CPU thread, which drops
+// whatever it was running before
+fn start_game(
+    mut processor: Processor,
+    game: &Game,
+    window: &Window,
+    screen: &Arc<Mutex<Screen>>,
+    beeper: Option<&Arc<Mutex<Beeper>>>,
+    speed: &Arc<Mutex<Speed>>,
+    processor_sender: &mpsc::Sender<Processor>,
+) {
+    window.set_title(&game.title);
+    {
+        let mut screen = screen.lock().unwrap();
+        screen.palette = game.palette;
+        screen.set_resolution(REAL_WIDTH as u32, processor.display_height() as u32);
+    }
+    {
+        let mut speed = speed.lock().unwrap();
+        speed.instructions_per_frame = processor.instructions_per_frame;
+        speed.timing = processor.timing;
+    }
+    if let Some(beeper) = beeper {
+        let mut beeper = beeper.lock().unwrap();
+        beeper.set_frequency(game.beep_frequency);
+        beeper.set_volume(game.volume);
+    }
+
+    processor.screen = Some(Arc::clone(screen));
+    processor.render().unwrap();
+    processor_sender.send(processor).unwrap();
+}
+
+fn show_doc(doc: Option<&DocViewer>, screen: &Arc<Mutex<Screen>>) {
+    let lines = match doc {
+        Some(doc) => doc.lines(),
+        None => vec![
+            (
+                "No documentation found for this rom.".to_string(),
+                TEXT_COLOR,
+            ),
+            (String::new(), TEXT_COLOR),
+            ("F2 or Esc to close".to_string(), DIM_COLOR),
+        ],
+    };
+
+    let mut screen = screen.lock().unwrap();
+    screen.overlay.show_lines(&lines);
+    screen.render().unwrap();
+}
+
+fn show_launcher(launcher: &Launcher, can_resume: bool, screen: &Arc<Mutex<Screen>>) {
+    let mut screen = screen.lock().unwrap();
+    screen.overlay.show_lines(&launcher.lines(can_resume));
+    screen.render().unwrap();
+}
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match Cli::parse(env::args().skip(1).collect()) {
+        Ok(command) => command,
+        Err(e) => {
+            println!("{}. Exiting...", e);
+            return;
+        }
+    };
+
+    let result = match command {
+        Command::Run(options) => {
+            run(options);
+            Ok(())
+        }
+        Command::Disasm {
+            rom_path,
+            patch_path,
+        } => Commands::disasm(&rom_path, patch_path.as_deref()),
+        Command::Trace {
+            rom_path,
+            patch_path,
+            steps,
+            emulation,
+        } => Commands::trace(&rom_path, patch_path.as_deref(), steps, &emulation),
+        Command::Test {
+            rom_path,
+            patch_path,
+            frames,
+            emulation,
+        } => Commands::test(&rom_path, patch_path.as_deref(), frames, &emulation),
+        Command::Info {
+            rom_path,
+            patch_path,
+        } => Commands::info(&rom_path, patch_path.as_deref()),
+        Command::Import {
+            programs_path,
+            roms_dir,
+        } => Commands::import(&programs_path, roms_dir.as_deref()),
+        Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        println!("{}. Exiting...", e);
+    }
+}
 
-    if args.is_empty() {
-        println!("You must provide a rom as the first argument. Exiting...");
+fn run(options: RunOptions) {
+    if options.print_doc {
+        let Some(rom_path) = &options.rom_path else {
+            println!("--doc needs a rom to show the documentation of. Exiting...");
+            return;
+        };
+        match Loader::load_doc(Path::new(rom_path)) {
+            Some(text) => DocViewer::new(&Loader::doc_title(Path::new(rom_path)), &text).print(),
+            None => println!("No documentation found for {}", rom_path),
+        }
         return;
     }
 
-    let rom_path = &args[0];
+    let config = Config::load();
+    let database = RomDatabase::load();
+    let scale = options
+        .scale
+        .or(config.settings.scale)
+        .unwrap_or(DEFAULT_SCALE);
 
-    let pressed_keys = Arc::new(Mutex::new([false; 16]));
-    let shared_pressed_keys = Arc::clone(&pressed_keys);
+    // A missing default directory just means there's no launcher, but one that was asked for
+    // has to exist
+    let mut launcher = match options
+        .rom_dir
+        .as_ref()
+        .or(config.settings.rom_dir.as_ref())
+    {
+        Some(dir) => match Launcher::scan(dir) {
+            Ok(launcher) => Some(launcher),
+            Err(e) => {
+                println!(
+                    "Failed to read rom directory {}: {}. Exiting...",
+                    dir.display(),
+                    e
+                );
+                return;
+            }
+        },
+        None => Launcher::scan(Path::new(DEFAULT_ROM_DIR)).ok(),
+    };
+    let mut rom_path = options.rom_path.clone();
+    if rom_path.is_none() && launcher.as_ref().is_none_or(|l| l.entries.is_empty()) {
+        println!(
+            "You must provide a rom as the first argument, or a directory of roms with --rom-dir. Exiting..."
+        );
+        return;
+    }
 
-    let (_stream, stream_handle) =
-        rodio::OutputStream::try_default().expect("Unable to get audio output stream");
-    let sink = Sink::try_new(&stream_handle).unwrap();
+    let pressed_keys = Arc::new(Mutex::new([false; 16]));
+    let paused = Arc::new(Mutex::new(false));
+    let shared_paused = Arc::clone(&paused);
+    let speed = Arc::new(Mutex::new(Speed::new(DEFAULT_INSTRUCTIONS_PER_FRAME)));
+    let shared_speed = Arc::clone(&speed);
 
-    let wave = SquareWave::new(240.0, 44100);
-    let source = wave
-        .take_duration(Duration::from_secs_f32(0.25))
-        .amplify(0.20)
-        .repeat_infinite();
-    sink.append(source);
-    sink.pause();
+    let mut first_game = None;
+    if let Some(rom_path) = &rom_path {
+        match load_game(rom_path, &options, &config, &database, &pressed_keys) {
+            Ok(loaded) => first_game = Some(loaded),
+            Err(e) => {
+                println!("{}. Exiting...", e);
+                return;
+            }
+        }
+    }
 
-    let mut processor = Processor::new(pressed_keys);
-    Loader::load_rom(&mut processor, rom_path);
+    // The stream has to outlive the beeper, so it's kept here even though it isn't used
+    let audio = (!options.mute)
+        .then(|| rodio::OutputStream::try_default().expect("Unable to get audio output stream"));
+    let beeper = audio
+        .as_ref()
+        .map(|(_, stream_handle)| Arc::new(Mutex::new(Beeper::new(stream_handle))));
+    let shared_beeper = beeper.clone();
 
-    let size = LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT);
     let event_loop = EventLoop::new().unwrap();
     let window_builder = WindowBuilder::new()
-        .with_title("Chip8 Interpreter")
-        .with_inner_size(size)
-        .with_max_inner_size(size)
-        .with_min_inner_size(size);
+        .with_title(WINDOW_TITLE)
+        .with_inner_size(LogicalSize::new(
+            REAL_WIDTH as u32 * scale,
+            REAL_HEIGHT as u32 * scale,
+        ))
+        .with_min_inner_size(LogicalSize::new(REAL_WIDTH as u32, REAL_HEIGHT as u32));
     let window = window_builder.build(&event_loop).unwrap();
-    let surface_texture = SurfaceTexture::new(WINDOW_WIDTH, WINDOW_HEIGHT, &window);
+    let window_size = window.inner_size();
+    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
     let mut pixels = Pixels::new(REAL_WIDTH as u32, REAL_HEIGHT as u32, surface_texture).unwrap();
     pixels
         .resize_buffer(REAL_WIDTH as u32, REAL_HEIGHT as u32)
         .unwrap();
-    pixels.clear_color(Color::BLACK);
-    pixels.render().unwrap();
 
-    processor.pixels = Some(pixels);
-    let mut last_timer_tick = Instant::now();
+    let screen = Screen::new(
+        pixels,
+        (window_size.width, window_size.height),
+        options.scale_mode,
+    );
+    screen.render().unwrap();
+    let screen = Arc::new(Mutex::new(screen));
+
+    let (processor_sender, processor_receiver) = mpsc::channel::<Processor>();
+    let mut game = None;
+    let mut launcher_open = false;
+    let mut doc_open = false;
+    match first_game {
+        Some((processor, loaded)) => {
+            start_game(
+                processor,
+                &loaded,
+                &window,
+                &screen,
+                beeper.as_ref(),
+                &speed,
+                &processor_sender,
+            );
+            game = Some(loaded);
+        }
+        None => launcher_open = true,
+    }
 
     thread::spawn(move || {
+        let mut processor: Option<Processor> = None;
+        let mut scheduler = Scheduler::new();
+
         loop {
-            // 700 instructions per second
-            if processor.last_execution.elapsed().as_millis() >= (1000 / 700) {
-                processor.execute();
-                processor.last_execution = Instant::now();
+            // Nothing to run until the first game is picked from the launcher
+            let new_processor = match processor {
+                Some(_) => processor_receiver.try_recv().ok(),
+                None => processor_receiver.recv().ok(),
+            };
+            if new_processor.is_some() {
+                processor = new_processor;
+                scheduler.reset();
             }
 
-            if processor.sound_timer > 0 {
-                sink.play();
-            } else {
-                sink.pause();
-            }
+            let speed = *shared_speed.lock().unwrap();
+            let running = match processor.as_mut() {
+                Some(running) if !*shared_paused.lock().unwrap() => running,
+                _ => {
+                    if let Some(beeper) = &shared_beeper {
+                        beeper.lock().unwrap().pause();
+                    }
+                    thread::sleep(speed.frame_duration());
+                    scheduler.reset();
+                    continue;
+                }
+            };
 
-            // Update timers 60 times a second
-            if last_timer_tick.elapsed().as_millis() >= (1000 / 60) {
-                processor.delay_timer = if processor.delay_timer > 0 {
-                    processor.delay_timer - 1
-                } else {
-                    0
-                };
-                processor.sound_timer = if processor.sound_timer > 0 {
-                    processor.sound_timer - 1
+            running.instructions_per_frame = speed.instructions_per_frame;
+            running.run_frame();
+
+            if let Some(beeper) = &shared_beeper {
+                let mut beeper = beeper.lock().unwrap();
+                if let Some(frequency) = running.tone_frequency {
+                    beeper.set_frequency(frequency);
+                }
+                if running.sound_timer > 0 {
+                    beeper.play();
                 } else {
-                    0
-                };
-                last_timer_tick = Instant::now();
+                    beeper.pause();
+                }
+            }
+
+            // A crashed rom is stopped and left on screen with the error over it, until another
+            // one is picked from the launcher
+            if let Some(Halt::Error(error)) = running.halt {
+                println!("{}", error);
+                if let Some(screen) = &running.screen {
+                    let mut screen = screen.lock().unwrap();
+                    let lines = [(format!("The rom crashed. {}.", error), TEXT_COLOR)];
+                    screen.overlay.show_lines(&lines);
+                    screen.render().unwrap();
+                }
+                if let Some(beeper) = &shared_beeper {
+                    beeper.lock().unwrap().pause();
+                }
+                processor = None;
+                continue;
             }
+
+            scheduler.wait_for_next_frame(speed.frame_duration());
         }
     });
 
     let mut input = WinitInputHelper::new();
+    // When the speed indicator should disappear after the speed was last changed
+    let mut speed_shown_until: Option<Instant> = None;
+
+    if launcher_open {
+        if let Some(launcher) = &launcher {
+            show_launcher(launcher, false, &screen);
+        }
+    }
 
     event_loop
         .run(move |event, elwt| {
             if input.update(&event) {
-                for (ind, i) in KEY_BINDINGS.iter().enumerate() {
-                    shared_pressed_keys.lock().unwrap()[ind] = input.key_held(*i);
+                if let (true, Some(launcher)) = (launcher_open, launcher.as_mut()) {
+                    let moves = [
+                        (KeyCode::ArrowUp, -1),
+                        (KeyCode::ArrowDown, 1),
+                        (KeyCode::PageUp, -10),
+                        (KeyCode::PageDown, 10),
+                    ];
+                    let mut moved = false;
+                    for (key, offset) in moves {
+                        if input.key_pressed(key) {
+                            launcher.move_selection(offset);
+                            moved = true;
+                        }
+                    }
+
+                    if input.key_pressed(KeyCode::Enter) {
+                        if let Some(entry) = launcher.selected_entry() {
+                            match load_game(
+                                &entry.path.to_string_lossy(),
+                                &options,
+                                &config,
+                                &database,
+                                &pressed_keys,
+                            ) {
+                                Ok((processor, loaded)) => {
+                                    start_game(
+                                        processor,
+                                        &loaded,
+                                        &window,
+                                        &screen,
+                                        beeper.as_ref(),
+                                        &speed,
+                                        &processor_sender,
+                                    );
+                                    rom_path = Some(entry.path.to_string_lossy().to_string());
+                                    game = Some(loaded);
+                                    launcher_open = false;
+                                }
+                                Err(e) => println!("{}", e),
+                            }
+                        }
+                    } else if input.key_pressed(KeyCode::Escape) {
+                        if game.is_none() {
+                            elwt.exit();
+                            return;
+                        }
+                        launcher_open = false;
+                    }
+
+                    if launcher_open {
+                        if moved {
+                            show_launcher(launcher, game.is_some(), &screen);
+                        }
+                    } else {
+                        screen.lock().unwrap().overlay.hide();
+                        screen.lock().unwrap().render().unwrap();
+                        *paused.lock().unwrap() = false;
+                    }
+                } else if let (true, Some(game)) = (doc_open, game.as_mut()) {
+                    let scrolls = [
+                        (KeyCode::ArrowUp, -1),
+                        (KeyCode::ArrowDown, 1),
+                        (KeyCode::PageUp, -DocViewer::page_size()),
+                        (KeyCode::PageDown, DocViewer::page_size()),
+                    ];
+                    let mut scrolled = false;
+                    if let Some(doc) = game.doc.as_mut() {
+                        for (key, offset) in scrolls {
+                            if input.key_pressed(key) {
+                                doc.scroll(offset);
+                                scrolled = true;
+                            }
+                        }
+                    }
+
+                    if input.key_pressed(KeyCode::F2) || input.key_pressed(KeyCode::Escape) {
+                        doc_open = false;
+                        screen.lock().unwrap().overlay.hide();
+                        screen.lock().unwrap().render().unwrap();
+                        *paused.lock().unwrap() = false;
+                    } else if scrolled {
+                        show_doc(game.doc.as_ref(), &screen);
+                    }
+                } else if let Some(game) = game.as_mut() {
+                    for (ind, i) in pressed_keys.lock().unwrap().iter_mut().enumerate() {
+                        *i = game.keymap.is_held(ind, &input);
+                    }
+                    for (ind, i) in game.second_keys.lock().unwrap().iter_mut().enumerate() {
+                        *i = game.keymap.is_held_on_second_keypad(ind, &input);
+                    }
+
+                    if input.key_pressed(KeyCode::F1) {
+                        let mut screen = screen.lock().unwrap();
+                        if screen.overlay.visible && screen.overlay.status.is_none() {
+                            screen.overlay.hide();
+                        } else {
+                            screen.overlay.show_lines(&game.key_help);
+                        }
+                        screen.render().unwrap();
+                    }
+
+                    // Pauses the game to read its documentation
+                    if input.key_pressed(KeyCode::F2) {
+                        *paused.lock().unwrap() = true;
+                        *pressed_keys.lock().unwrap() = [false; 16];
+                        *game.second_keys.lock().unwrap() = [false; 16];
+                        doc_open = true;
+                        show_doc(game.doc.as_ref(), &screen);
+                    }
+
+                    // Volume down and up, remembered in the config if save_settings is on
+                    let volume_keys = [(KeyCode::F7, -0.05), (KeyCode::F8, 0.05)];
+                    for (key, change) in volume_keys {
+                        if let (true, Some(beeper)) = (input.key_pressed(key), &beeper) {
+                            let beeper = beeper.lock().unwrap();
+                            beeper.set_volume(beeper.volume() + change);
+                            game.volume = beeper.volume();
+                            println!("Volume: {:.0}%", game.volume * 100.0);
+
+                            let sha1 = game.volume_in_rom_config.then_some(game.sha1.as_str());
+                            let volume = (game.volume * 100.0).round() as f64 / 100.0;
+                            config.save(sha1, "volume", TomlValue::Float(volume));
+                        }
+                    }
+
+                    // Speed down and up, saved for this rom if save_settings is on. VIP timing
+                    // has a fixed speed, so they only show it.
+                    let speed_keys = [(KeyCode::F3, false), (KeyCode::F4, true)];
+                    for (key, faster) in speed_keys {
+                        if input.key_pressed(key) {
+                            let mut speed = speed.lock().unwrap();
+                            speed_shown_until = Some(Instant::now() + SPEED_INDICATOR_DURATION);
+                            if speed.timing == Timing::Vip {
+                                continue;
+                            }
+
+                            if faster {
+                                speed.faster();
+                            } else {
+                                speed.slower();
+                            }
+                            println!(
+                                "Speed: {} instructions per frame",
+                                speed.instructions_per_frame
+                            );
+                            config.save(
+                                Some(&game.sha1),
+                                "speed",
+                                TomlValue::Integer(speed.instructions_per_frame as i64),
+                            );
+                        }
+                    }
+
+                    // Fast-forward while Tab is held, slow motion toggled with F5
+                    {
+                        let mut speed = speed.lock().unwrap();
+                        let mode = speed.mode;
+                        if input.key_held(KeyCode::Tab) {
+                            speed.mode = SpeedMode::FastForward;
+                        } else if speed.mode == SpeedMode::FastForward {
+                            speed.mode = SpeedMode::Normal;
+                        }
+                        if input.key_pressed(KeyCode::F5) {
+                            speed.mode = match speed.mode {
+                                SpeedMode::SlowMotion => SpeedMode::Normal,
+                                _ => SpeedMode::SlowMotion,
+                            };
+                        }
+                        if speed.mode != mode && speed.mode == SpeedMode::Normal {
+                            speed_shown_until = Some(Instant::now() + SPEED_INDICATOR_DURATION);
+                        }
+                    }
+
+                    // Pauses the game and goes back to the rom list
+                    if let (true, Some(launcher)) =
+                        (input.key_pressed(KeyCode::Escape), launcher.as_mut())
+                    {
+                        *paused.lock().unwrap() = true;
+                        *pressed_keys.lock().unwrap() = [false; 16];
+                        *game.second_keys.lock().unwrap() = [false; 16];
+                        if let Some(rom_path) = &rom_path {
+                            launcher.select_path(Path::new(rom_path));
+                        }
+                        launcher_open = true;
+                        show_launcher(launcher, true, &screen);
+                    }
+                }
+
+                // The speed indicator stays up while fast-forwarding or in slow motion, and
+                // for a moment after the speed is changed. It gives way to other overlays.
+                if !launcher_open && !doc_open {
+                    let speed = *speed.lock().unwrap();
+                    let status = (speed.mode != SpeedMode::Normal
+                        || speed_shown_until.is_some_and(|until| Instant::now() < until))
+                    .then(|| speed.label());
+
+                    let mut screen = screen.lock().unwrap();
+                    let other_overlay = screen.overlay.visible && screen.overlay.status.is_none();
+                    if !other_overlay && screen.overlay.status != status {
+                        match &status {
+                            Some(text) => screen.overlay.show_status(text),
+                            None => screen.overlay.hide(),
+                        }
+                        screen.render().unwrap();
+                    }
+                }
+                match speed_shown_until {
+                    Some(until) if Instant::now() < until => {
+                        elwt.set_control_flow(ControlFlow::WaitUntil(until))
+                    }
+                    _ => {
+                        speed_shown_until = None;
+                        elwt.set_control_flow(ControlFlow::Wait);
+                    }
+                }
+
+                if input.key_pressed(KeyCode::F11) {
+                    window.set_fullscreen(match window.fullscreen() {
+                        Some(_) => None,
+                        None => Some(Fullscreen::Borderless(None)),
+                    });
+                }
+
+                if let Some(size) = input.window_resized() {
+                    screen.lock().unwrap().resize(size.width, size.height);
                 }
             }
 